@@ -0,0 +1,91 @@
+use anyhow::{anyhow, bail, Result};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::auth::SupabaseAuth;
+use crate::config::{AccountCommand, DevicesCommand, KeyStoreArgs, UnstableCommand};
+use crate::key_manager;
+use crate::key_storage::KeyEnvironment;
+use crate::net::HttpOptions;
+
+// `unstable devices`, `unstable account` and `unstable revoke-key`: the
+// account API's device management, stats and key revocation, for the account
+// whose --account-token is given. The API only documents creating mining
+// tokens, so these endpoints are our best guess and kept apart from the
+// stable commands until it publishes them.
+
+/// The API client, base URL and account token for `store`.
+fn client(store: &KeyStoreArgs, command: &str) -> Result<(SupabaseAuth, String, String)> {
+    let account_token = store
+        .keys
+        .account_token
+        .as_ref()
+        .ok_or_else(|| anyhow!("`{}` needs --account-token", command))?;
+    let auth = SupabaseAuth::new(&HttpOptions::from_config(&store.api)?)?;
+    let api_url = KeyEnvironment::from_config(&store.api).api_url;
+    Ok((auth, api_url, account_token.expose().to_string()))
+}
+
+pub async fn run_unstable_command(command: UnstableCommand) -> Result<()> {
+    warn!("`unstable` commands use NockPool API endpoints without a published contract; they may change or fail");
+    match command {
+        UnstableCommand::Devices { command } => run_devices_command(command).await,
+        UnstableCommand::Account { command } => run_account_command(command).await,
+        UnstableCommand::RevokeKey(store) => key_manager::run_revoke_key_command(&store).await,
+    }
+}
+
+async fn run_devices_command(command: DevicesCommand) -> Result<()> {
+    let (auth, api_url, account_token) = client(command.store(), "unstable devices")?;
+
+    match command {
+        DevicesCommand::List(_) => {
+            let devices = auth.list_devices(&account_token, &api_url).await?;
+            if devices.is_empty() {
+                println!("No devices registered to this account");
+                return Ok(());
+            }
+            println!("{:<38}{:<32}{:<12}{:<12}STATUS", "ID", "NICKNAME", "CREATED", "LAST SEEN");
+            let date = |at: Option<chrono::DateTime<chrono::Utc>>| at.map_or("-".to_string(), |at| at.format("%Y-%m-%d").to_string());
+            for device in &devices {
+                println!(
+                    "{:<38}{:<32}{:<12}{:<12}{}",
+                    device.id,
+                    device.nickname.as_deref().unwrap_or("-"),
+                    date(device.created_at),
+                    date(device.last_seen_at),
+                    if device.revoked { "revoked" } else { "active" }
+                );
+            }
+        }
+        DevicesCommand::Rename { id, nickname, .. } => {
+            let device = auth.rename_device(&account_token, &id, &nickname, &api_url).await?;
+            println!("Renamed device {} to {}", device.id, device.nickname.as_deref().unwrap_or(&nickname));
+        }
+        DevicesCommand::Revoke { id, .. } => {
+            if !auth.revoke_device(&account_token, &id, &api_url).await? {
+                bail!("No device {} on this account", id);
+            }
+            println!("Revoked device {}; the pool will refuse its key from now on", id);
+        }
+    }
+    Ok(())
+}
+
+async fn run_account_command(command: AccountCommand) -> Result<()> {
+    match command {
+        AccountCommand::Stats(store) => {
+            let (auth, api_url, account_token) = client(&store, "unstable account stats")?;
+            let stats = auth.account_stats(&account_token, &api_url).await?;
+            let width = stats.stats.keys().map(String::len).max().unwrap_or_default() + 2;
+            for (name, value) in &stats.stats {
+                let value = match value {
+                    Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                };
+                println!("{:<width$}{}", format!("{}:", name), value, width = width);
+            }
+        }
+    }
+    Ok(())
+}
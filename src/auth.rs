@@ -1,10 +1,21 @@
 use anyhow::{anyhow, Result};
-use reqwest::Client;
+use chrono::{DateTime, Utc};
+use reqwest::{Client, RequestBuilder, Response, StatusCode, Url};
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
-use tokio::time::sleep;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use uuid::Uuid;
 
-// API authentication for mining token creation
+use crate::net::{build_http_client, HttpOptions};
+use crate::retry::{self, RetryPolicy};
+
+/// Lets the API deduplicate a retried create.
+const IDEMPOTENCY_KEY: &str = "Idempotency-Key";
+
+// Client for the NockPool account API: mining token creation and
+// revocation, and managing the devices (mining tokens) of an account.
+// Every call authenticates with the account token and goes through the
+// retry policy in `retry`.
 
 #[derive(Debug, Serialize)]
 struct CreateMiningTokenRequest {
@@ -12,77 +23,416 @@ struct CreateMiningTokenRequest {
     expires_days: Option<u32>,
 }
 
+/// What to revoke: a mining token, named by itself or by its device id.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum RevokeRequest<'a> {
+    MiningToken(&'a str),
+    DeviceId(&'a str),
+}
+
 #[derive(Debug, Deserialize)]
 struct MiningTokenResponse {
     mining_token: String,
 }
 
+#[derive(Debug, Serialize)]
+struct RenameDeviceRequest<'a> {
+    nickname: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct DevicesResponse {
+    devices: Vec<Device>,
+}
+
+/// A device registered to the account, i.e. one mining token.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Device {
+    pub id: String,
+    #[serde(default)]
+    pub nickname: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_seen_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub revoked: bool,
+}
+
+/// Account statistics as reported by the API, by name.
+#[derive(Debug, Deserialize)]
+pub struct AccountStats {
+    #[serde(flatten)]
+    pub stats: BTreeMap<String, Value>,
+}
+
 pub struct SupabaseAuth {
     client: Client,
+    retry: RetryPolicy,
 }
 
 impl SupabaseAuth {
-    pub fn new() -> Self {
-        Self {
-            client: Client::new(),
-        }
+    pub fn new(options: &HttpOptions) -> Result<Self> {
+        Ok(Self {
+            client: build_http_client(options)?,
+            retry: options.retry,
+        })
     }
 
-    pub async fn create_mining_token(&self, account_token: &str, device_nickname: Option<String>, api_base_url: &str) -> Result<String> {
+    pub async fn create_mining_token(
+        &self,
+        account_token: &str,
+        device_nickname: Option<String>,
+        expires_days: Option<u32>,
+        api_base_url: &str,
+    ) -> Result<String> {
         let api_url = format!("{}/api/v1/mining-tokens", api_base_url);
         
         let request = CreateMiningTokenRequest {
             device_nickname,
-            expires_days: None, // Set timestamp to null
+            expires_days, // None never expires
         };
 
-        const MAX_RETRIES: u32 = 5;
-        let mut retry_count = 0;
+        // The same key on every attempt lets the API recognise a retry of a
+        // create that went through, rather than minting a second token.
+        let request = self
+            .client
+            .post(&api_url)
+            .header(IDEMPOTENCY_KEY, Uuid::new_v4().to_string())
+            .json(&request);
+        let response = self.execute(request, account_token, "create mining tokens").await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(anyhow!("Mining token creation endpoint not found - this feature may not be available yet"));
+        }
+        let response = Self::check(response, "create mining tokens").await?;
+        Ok(response.json::<MiningTokenResponse>().await?.mining_token)
+    }
 
-        loop {
-            let response = self
-                .client
-                .post(&api_url)
-                .header("Authorization", format!("Bearer {}", account_token))
-                .header("Content-Type", "application/json")
-                .json(&request)
-                .send()
-                .await?;
-
-            if response.status().is_success() {
-                let response_data: MiningTokenResponse = response.json().await?;
-                return Ok(response_data.mining_token);
-            }
+    /// Sends an authenticated request under the retry policy, returning the
+    /// final response whatever its status.
+    async fn execute(&self, request: RequestBuilder, account_token: &str, action: &str) -> Result<Response> {
+        retry::send(&self.retry, request.bearer_auth(account_token), action).await
+    }
 
-            let status = response.status();
-            let error_text = response.text().await?;
-            
-            match status.as_u16() {
-                401 => return Err(anyhow!("Invalid or expired account token")),
-                403 => return Err(anyhow!("Account token does not have permission to create mining tokens")),
-                404 => return Err(anyhow!("Mining token creation endpoint not found - this feature may not be available yet")),
-                429 => {
-                    if retry_count >= MAX_RETRIES {
-                        return Err(anyhow!("Rate limit exceeded - maximum retries ({}) reached", MAX_RETRIES));
-                    }
-                    
-                    // Exponential backoff: 1, 2, 4, 8, 10 seconds (capped at 10)
-                    let delay_seconds = std::cmp::min(1u64 << retry_count, 10);
-                    tracing::warn!("Rate limit hit, retrying in {} seconds (attempt {}/{})", delay_seconds, retry_count + 1, MAX_RETRIES + 1);
-                    
-                    sleep(Duration::from_secs(delay_seconds)).await;
-                    retry_count += 1;
-                    continue;
-                }
-                _ => return Err(anyhow!("Mining token creation failed ({}): {}", status, error_text)),
-            }
+    /// Turns an error status into an error that says what `action` was
+    /// being attempted.
+    async fn check(response: Response, action: &str) -> Result<Response> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
         }
+        let error_text = response.text().await.unwrap_or_default();
+        Err(match status {
+            StatusCode::UNAUTHORIZED => anyhow!("Invalid or expired account token"),
+            StatusCode::FORBIDDEN => anyhow!("Account token does not have permission to {}", action),
+            StatusCode::TOO_MANY_REQUESTS => anyhow!("Rate limited while trying to {}; try again later", action),
+            _ => anyhow!("Failed to {} ({}): {}", action, status, error_text),
+        })
+    }
+
+    /// `execute` followed by `check`.
+    async fn send(&self, request: RequestBuilder, account_token: &str, action: &str) -> Result<Response> {
+        let response = self.execute(request, account_token, action).await?;
+        Self::check(response, action).await
+    }
+
+    fn device_url(api_base_url: &str, device_id: &str) -> Result<Url> {
+        let mut url = Url::parse(&format!("{}/api/v1/devices", api_base_url))?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("Invalid API URL '{}'", api_base_url))?
+            .push(device_id);
+        Ok(url)
+    }
+
+    pub async fn list_devices(&self, account_token: &str, api_base_url: &str) -> Result<Vec<Device>> {
+        let api_url = format!("{}/api/v1/devices", api_base_url);
+        let response = self.send(self.client.get(&api_url), account_token, "list devices").await?;
+        Ok(response.json::<DevicesResponse>().await?.devices)
+    }
+
+    pub async fn rename_device(&self, account_token: &str, device_id: &str, nickname: &str, api_base_url: &str) -> Result<Device> {
+        let request = self.client.patch(Self::device_url(api_base_url, device_id)?).json(&RenameDeviceRequest { nickname });
+        let response = self.send(request, account_token, "rename the device").await?;
+        Ok(response.json().await?)
+    }
+
+    /// Revokes a device's mining token; the pool refuses it from then on.
+    /// False when the account has no such device.
+    pub async fn revoke_device(&self, account_token: &str, device_id: &str, api_base_url: &str) -> Result<bool> {
+        self.revoke(account_token, RevokeRequest::DeviceId(device_id), api_base_url).await
     }
 
-    pub async fn get_or_create_mining_token(&self, account_token: &str, device_nickname: Option<String>, api_base_url: &str) -> Result<String> {
+    pub async fn account_stats(&self, account_token: &str, api_base_url: &str) -> Result<AccountStats> {
+        let api_url = format!("{}/api/v1/account/stats", api_base_url);
+        let response = self.send(self.client.get(&api_url), account_token, "fetch account stats").await?;
+        Ok(response.json().await?)
+    }
+
+    pub async fn get_or_create_mining_token(
+        &self,
+        account_token: &str,
+        device_nickname: Option<String>,
+        expires_days: Option<u32>,
+        api_base_url: &str,
+    ) -> Result<String> {
         tracing::info!("Creating mining token using account token...");
-        let mining_token = self.create_mining_token(account_token, device_nickname, api_base_url).await?;
+        let mining_token = self.create_mining_token(account_token, device_nickname, expires_days, api_base_url).await?;
         tracing::info!("Successfully created mining token");
         Ok(mining_token)
     }
-}
\ No newline at end of file
+
+    /// Revokes a mining token; the pool refuses it from then on. False when
+    /// the API doesn't know the token, e.g. because it was already revoked.
+    pub async fn revoke_mining_token(&self, account_token: &str, mining_token: &str, api_base_url: &str) -> Result<bool> {
+        self.revoke(account_token, RevokeRequest::MiningToken(mining_token), api_base_url).await
+    }
+
+    async fn revoke(&self, account_token: &str, request: RevokeRequest<'_>, api_base_url: &str) -> Result<bool> {
+        let api_url = format!("{}/api/v1/mining-tokens/revoke", api_base_url);
+        let request = self.client.post(&api_url).json(&request);
+        let response = self.execute(request, account_token, "revoke the mining token").await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        Self::check(response, "revoke the mining token").await?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Minimal HTTP proxy stand-in: answers a single request and hands back its request line.
+    async fn proxy_stand_in(listener: TcpListener, body: &'static str) -> String {
+        let (stream, _) = listener.accept().await.unwrap();
+        answer_request(stream, body).await
+    }
+
+    /// Minimal SOCKS5 stand-in: accepts one unauthenticated CONNECT by host
+    /// name, then answers the tunnelled request itself. Hands back the
+    /// requested destination and the request line.
+    async fn socks5_stand_in(listener: TcpListener, body: &'static str) -> (String, String) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+
+        let mut greeting = [0u8; 2];
+        stream.read_exact(&mut greeting).await.unwrap();
+        assert_eq!(greeting[0], 5);
+        let mut methods = vec![0u8; greeting[1] as usize];
+        stream.read_exact(&mut methods).await.unwrap();
+        assert!(methods.contains(&0), "client offered no unauthenticated method");
+        stream.write_all(&[5, 0]).await.unwrap();
+
+        // VER CMD RSV ATYP, then the host name a socks5h client leaves to us.
+        let mut request = [0u8; 5];
+        stream.read_exact(&mut request).await.unwrap();
+        assert_eq!(request[..4], [5, 1, 0, 3]);
+        let mut host = vec![0u8; request[4] as usize];
+        stream.read_exact(&mut host).await.unwrap();
+        let mut port = [0u8; 2];
+        stream.read_exact(&mut port).await.unwrap();
+        stream.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).await.unwrap();
+
+        let destination = format!("{}:{}", String::from_utf8(host).unwrap(), u16::from_be_bytes(port));
+        (destination, answer_request(stream, body).await)
+    }
+
+    async fn answer_request(stream: TcpStream, body: &'static str) -> String {
+        let mut reader = BufReader::new(stream);
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).await.unwrap();
+
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).await.unwrap();
+            if header == "\r\n" {
+                break;
+            }
+            if let Some(value) = header.to_ascii_lowercase().strip_prefix("content-length:") {
+                content_length = value.trim().parse().unwrap();
+            }
+        }
+        let mut request_body = vec![0; content_length];
+        reader.read_exact(&mut request_body).await.unwrap();
+
+        let response = format!(
+            "HTTP/1.1 201 Created\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        reader.get_mut().write_all(response.as_bytes()).await.unwrap();
+        request_line
+    }
+
+    /// Stand-in for the account API that only serves `nockacct_test`. It
+    /// revokes `nock_live` and device `dev-1`, refuses to revoke
+    /// `nock_locked`, and knows no other token.
+    pub(crate) async fn mock_api() -> String {
+        use axum::extract::{Path, Request};
+        use axum::http::{header, StatusCode};
+        use axum::middleware::{self, Next};
+        use axum::response::IntoResponse;
+        use axum::routing::{get, patch, post};
+        use axum::{Json, Router};
+        use serde_json::json;
+
+        let app = Router::new()
+            .route("/api/v1/devices", get(|| async {
+                Json(json!({"devices": [
+                    {"id": "dev-1", "nickname": "rig-1", "created_at": "2026-01-02T03:04:05Z", "revoked": false},
+                    {"id": "dev-2"}
+                ]}))
+            }))
+            .route(
+                "/api/v1/devices/{id}",
+                patch(|Path(id): Path<String>, Json(body): Json<Value>| async move {
+                    Json(json!({"id": id, "nickname": body["nickname"]}))
+                }),
+            )
+            .route("/api/v1/mining-tokens/revoke", post(|Json(body): Json<Value>| async move {
+                match (body["mining_token"].as_str(), body["device_id"].as_str()) {
+                    (Some("nock_live"), None) | (None, Some("dev-1")) => StatusCode::NO_CONTENT,
+                    (Some("nock_locked"), None) => StatusCode::FORBIDDEN,
+                    _ => StatusCode::NOT_FOUND,
+                }
+            }))
+            .route("/api/v1/account/stats", get(|| async { Json(json!({"active_devices": 2, "accepted_shares": 1234})) }))
+            .layer(middleware::from_fn(|request: Request, next: Next| async move {
+                match request.headers().get(header::AUTHORIZATION) {
+                    Some(value) if value == "Bearer nockacct_test" => next.run(request).await,
+                    _ => StatusCode::UNAUTHORIZED.into_response(),
+                }
+            }));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        api_url
+    }
+
+    #[tokio::test]
+    async fn test_device_management() {
+        let api_url = mock_api().await;
+        let auth = SupabaseAuth::new(&HttpOptions::default()).unwrap();
+
+        let devices = auth.list_devices("nockacct_test", &api_url).await.unwrap();
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].nickname.as_deref(), Some("rig-1"));
+        assert!(devices[0].created_at.is_some());
+        assert_eq!(devices[1].nickname, None);
+
+        let renamed = auth.rename_device("nockacct_test", "dev-1", "garage", &api_url).await.unwrap();
+        assert_eq!(renamed.nickname.as_deref(), Some("garage"));
+
+        assert!(auth.revoke_device("nockacct_test", "dev-1", &api_url).await.unwrap());
+        assert!(!auth.revoke_device("nockacct_test", "dev-9", &api_url).await.unwrap());
+        assert!(auth.revoke_mining_token("nockacct_test", "nock_live", &api_url).await.unwrap());
+        assert!(!auth.revoke_mining_token("nockacct_test", "nock_gone", &api_url).await.unwrap());
+
+        let stats = auth.account_stats("nockacct_test", &api_url).await.unwrap();
+        assert_eq!(stats.stats["accepted_shares"], 1234);
+
+        let error = auth.list_devices("nockacct_wrong", &api_url).await.unwrap_err();
+        assert_eq!(error.to_string(), "Invalid or expired account token");
+    }
+
+    #[tokio::test]
+    async fn test_create_mining_token_retries_with_one_idempotency_key() {
+        use axum::http::{header, HeaderMap, StatusCode};
+        use axum::response::IntoResponse;
+        use axum::routing::post;
+        use axum::{Json, Router};
+        use serde_json::json;
+        use std::sync::{Arc, Mutex};
+        use std::time::Duration;
+
+        let keys = Arc::new(Mutex::new(Vec::new()));
+        let seen = keys.clone();
+        let app = Router::new().route(
+            "/api/v1/mining-tokens",
+            post(move |headers: HeaderMap| async move {
+                let mut keys = seen.lock().unwrap();
+                keys.push(headers[IDEMPOTENCY_KEY].to_str().unwrap().to_string());
+                match keys.len() {
+                    1 => StatusCode::SERVICE_UNAVAILABLE.into_response(),
+                    2 => (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, "0")]).into_response(),
+                    _ => (StatusCode::CREATED, Json(json!({"mining_token": "nock_once"}))).into_response(),
+                }
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let retry = RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_secs(1),
+        };
+        let auth = SupabaseAuth::new(&HttpOptions { retry, ..Default::default() }).unwrap();
+        let token = auth.create_mining_token("nockacct_test", None, None, &api_url).await.unwrap();
+
+        assert_eq!(token, "nock_once");
+        {
+            let keys = keys.lock().unwrap();
+            assert_eq!(keys.len(), 3);
+            assert!(keys.iter().all(|key| *key == keys[0]));
+        }
+
+        // A new create is a new request as far as the API is concerned.
+        auth.create_mining_token("nockacct_test", None, None, &api_url).await.unwrap();
+        let keys = keys.lock().unwrap();
+        assert_ne!(keys[3], keys[0]);
+    }
+
+    #[tokio::test]
+    async fn test_create_mining_token_via_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = format!("http://{}", listener.local_addr().unwrap());
+        let stand_in = tokio::spawn(proxy_stand_in(listener, r#"{"mining_token":"nock_proxied"}"#));
+
+        let auth = SupabaseAuth::new(&HttpOptions {
+            proxy: Some(proxy),
+            ..Default::default()
+        })
+        .unwrap();
+        let token = auth
+            .create_mining_token("nockacct_test", None, None, "http://nockpool.invalid")
+            .await
+            .unwrap();
+
+        assert_eq!(token, "nock_proxied");
+        assert_eq!(
+            stand_in.await.unwrap().trim_end(),
+            "POST http://nockpool.invalid/api/v1/mining-tokens HTTP/1.1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_mining_token_via_socks5_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = format!("socks5h://{}", listener.local_addr().unwrap());
+        let stand_in = tokio::spawn(socks5_stand_in(listener, r#"{"mining_token":"nock_socks"}"#));
+
+        let auth = SupabaseAuth::new(&HttpOptions {
+            proxy: Some(proxy),
+            ..Default::default()
+        })
+        .unwrap();
+        let token = auth
+            .create_mining_token("nockacct_test", None, None, "http://nockpool.invalid")
+            .await
+            .unwrap();
+
+        assert_eq!(token, "nock_socks");
+        // The proxy resolves the name, so a host that only it can reach still works.
+        let (destination, request_line) = stand_in.await.unwrap();
+        assert_eq!(destination, "nockpool.invalid:80");
+        assert_eq!(request_line.trim_end(), "POST /api/v1/mining-tokens HTTP/1.1");
+    }
+}
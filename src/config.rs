@@ -1,23 +1,397 @@
-use clap::Parser;
+use anyhow::anyhow;
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-#[derive(Parser, Debug, Clone)]
-#[command(version, about, long_about = None)]
+use crate::key_storage;
+use crate::log_file::Rotation;
+use crate::schedule::Schedule;
+use crate::secrets::{self, Secret};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Hold a pool session and mine locally.
+    Standalone,
+    /// Hold the pool session and serve templates to LAN miners instead of mining.
+    Proxy,
+    /// Like `proxy`, but workers must authenticate with the farm secret over an
+    /// encrypted channel and each is assigned its own nonce range.
+    Coordinator,
+    /// Prove templates from a coordinator; holds no pool session or key.
+    Worker,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Compact coloured lines for reading in a terminal.
+    Text,
+    /// One JSON object per line, for Loki, Elasticsearch and friends.
+    Json,
+    /// `key=value` pairs, one event per line.
+    Logfmt,
+}
+
+/// A mining key with a share of the mining threads, written `LABEL:KEY:WEIGHT`.
+#[derive(Debug, Clone)]
+pub struct WeightedKey {
+    pub label: String,
+    pub key: Secret,
+    pub weight: u32,
+}
+
+impl FromStr for WeightedKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid weighted key '{}': expected LABEL:KEY:WEIGHT", s);
+        let (label, rest) = s.split_once(':').ok_or_else(invalid)?;
+        let (key, weight) = rest.rsplit_once(':').ok_or_else(invalid)?;
+        let weight = weight.parse::<u32>().map_err(|_| invalid())?;
+        if label.is_empty() || key.is_empty() || weight == 0 {
+            return Err(invalid());
+        }
+        Ok(Self {
+            label: label.to_string(),
+            key: Secret::new(key.to_string()),
+            weight,
+        })
+    }
+}
+
+/// Weighted keys from a file, one `LABEL:KEY:WEIGHT` per line.
+fn read_weighted_keys(path: &Path) -> anyhow::Result<Vec<WeightedKey>> {
+    secrets::read_lines(path)?
+        .into_iter()
+        .map(|(number, line)| {
+            // The error from parsing would echo the key.
+            line.expose()
+                .parse()
+                .map_err(|_| anyhow!("{} line {}: expected LABEL:KEY:WEIGHT", secrets::source(path), number))
+        })
+        .collect()
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Options for `run`, which is what happens when no subcommand is given.
+    #[command(flatten)]
+    pub run: Config,
+
+    #[command(flatten)]
+    pub log: LogArgs,
+
+    /// TOML file supplying defaults for `run` options. Defaults to
+    /// `config.toml` in the nockpool config directory.
+    #[arg(long = "config", value_name = "FILE", env = "NOCKPOOL_CONFIG", global = true)]
+    pub config_file: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Mine. This is the default when no subcommand is given.
+    Run(Box<Config>),
+    /// Time a proof on this machine and exit.
+    Bench,
+    /// Manage stored mining keys.
+    Keys {
+        #[command(subcommand)]
+        command: KeysCommand,
+    },
+    /// Unstable: account API calls the NockPool API has not published a
+    /// contract for. They may change, or fail if the server lacks them.
+    Unstable {
+        #[command(subcommand)]
+        command: UnstableCommand,
+    },
+    /// Show the effective configuration or write a starter config file.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Ask a running miner for its status through the control API.
+    Status(StatusArgs),
+    /// Check the config directory, stored key and connectivity to the pool.
+    Diagnose(DiagnoseArgs),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum KeysCommand {
+    /// List stored keys across all profiles.
+    List(KeyStoreArgs),
+    /// Show the stored keys of --profile, redacted.
+    Show(KeyStoreArgs),
+    /// Delete the stored keys of --profile, or every stored key with --all.
+    Clear {
+        /// Delete every stored key, in every profile and environment.
+        #[arg(long, default_value_t = false)]
+        all: bool,
+
+        #[command(flatten)]
+        store: KeyStoreArgs,
+    },
+    /// Create a new key for --profile with the account token and store it.
+    Create(KeyStoreArgs),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum UnstableCommand {
+    /// List, rename and revoke the devices registered to your account.
+    Devices {
+        #[command(subcommand)]
+        command: DevicesCommand,
+    },
+    /// Show your NockPool account.
+    Account {
+        #[command(subcommand)]
+        command: AccountCommand,
+    },
+    /// Revoke the stored key of --profile with the pool and delete it.
+    RevokeKey(KeyStoreArgs),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DevicesCommand {
+    /// List the account's devices.
+    List(KeyStoreArgs),
+    /// Give a device a new nickname.
+    Rename {
+        /// Device id, as shown by `unstable devices list`.
+        id: String,
+        nickname: String,
+
+        #[command(flatten)]
+        store: KeyStoreArgs,
+    },
+    /// Revoke a device's mining key; the pool refuses it from then on.
+    Revoke {
+        /// Device id, as shown by `unstable devices list`.
+        id: String,
+
+        #[command(flatten)]
+        store: KeyStoreArgs,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AccountCommand {
+    /// Show the account's mining statistics.
+    Stats(KeyStoreArgs),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print the options `run` would use and where each one comes from.
+    Show(Box<Config>),
+    /// Write a commented config file to start from.
+    Init {
+        /// Overwrite an existing config file.
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+}
+
+/// Where stored keys live and how they are created.
+#[derive(Args, Debug, Clone)]
+pub struct KeyArgs {
+    /// Account token for generating mining tokens (alternative to --key).
+    #[arg(long, env = "NOCKPOOL_ACCOUNT_TOKEN", hide_env_values = true)]
+    pub account_token: Option<Secret>,
+
+    /// Read the account token from this file, or from stdin if `-`.
+    #[arg(long, env = "NOCKPOOL_ACCOUNT_TOKEN_FILE", conflicts_with = "account_token")]
+    pub account_token_file: Option<PathBuf>,
+
+    /// Create mining keys that expire after this many days, and replace
+    /// them with fresh ones before they do.
+    #[arg(long, env = "NOCKPOOL_TOKEN_EXPIRY_DAYS", value_parser = clap::value_parser!(u32).range(1..))]
+    pub token_expiry_days: Option<u32>,
+
+    /// Stored key profile to use, e.g. one per pool account or environment.
+    #[arg(long, env = "NOCKPOOL_PROFILE", default_value = key_storage::DEFAULT_PROFILE)]
+    pub profile: String,
+
+    /// Encrypt the stored mining key with a passphrase, prompting for it
+    /// unless --key-passphrase or --key-passphrase-file is given.
+    #[arg(long, default_value_t = false)]
+    pub encrypt_key: bool,
+
+    /// Passphrase for the stored mining key. Implies --encrypt-key.
+    #[arg(long, env = "NOCKPOOL_KEY_PASSPHRASE", hide_env_values = true)]
+    pub key_passphrase: Option<Secret>,
+
+    /// Read the stored mining key's passphrase from this file. Implies --encrypt-key.
+    #[arg(long, env = "NOCKPOOL_KEY_PASSPHRASE_FILE")]
+    pub key_passphrase_file: Option<PathBuf>,
+}
+
+/// How to reach the NockPool API and pool server.
+#[derive(Args, Debug, Clone)]
+pub struct ApiArgs {
+    /// The `ip:port` of the nockpool server.
+    #[arg(long, default_value = "quiver.nockpool.com:27016")]
+    pub server_address: String,
+
+    /// Base URL for the NockPool API (for local development).
+    #[arg(long, env = "NOCKPOOL_API_URL", default_value = "https://nockpool.com")]
+    pub api_url: String,
+
+    /// HTTP(S) or SOCKS5 proxy for NockPool API requests, e.g. `socks5h://127.0.0.1:1080`.
+    /// Falls back to `HTTPS_PROXY` / `ALL_PROXY` when not set.
+    #[arg(long, env = "NOCKPOOL_PROXY")]
+    pub proxy: Option<String>,
+
+    /// Let the pool connection bypass the proxy. It has no proxy support, so
+    /// mining refuses to start with a proxy configured unless this is set.
+    #[arg(long, env = "NOCKPOOL_POOL_DIRECT", default_value_t = false)]
+    pub pool_direct: bool,
+
+    /// PEM bundle of additional CA certificates to trust for NockPool API requests.
+    #[arg(long, env = "NOCKPOOL_CA_FILE")]
+    pub ca_file: Option<PathBuf>,
+
+    /// Pin the NockPool API certificate, as `spki:<sha256-hex>` or `cert:<sha256-hex>`.
+    /// May be repeated; the connection is accepted if any certificate in the chain matches any pin.
+    #[arg(long = "tls-pin", env = "NOCKPOOL_TLS_PINS", value_delimiter = ',')]
+    pub tls_pins: Vec<String>,
+
+    /// Seconds before a NockPool API request is abandoned (and retried).
+    #[arg(long, env = "NOCKPOOL_API_TIMEOUT", default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+    pub api_timeout: u64,
+
+    /// Seconds allowed for connecting to the NockPool API.
+    #[arg(long, env = "NOCKPOOL_API_CONNECT_TIMEOUT", default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    pub api_connect_timeout: u64,
+
+    /// How many times a NockPool API request is retried after connection errors,
+    /// timeouts, rate limiting or 502/503/504 responses. 0 disables retries.
+    #[arg(long, env = "NOCKPOOL_API_RETRIES", default_value_t = 5)]
+    pub api_retries: u32,
+}
+
+/// The key store and the API environment its keys belong to.
+#[derive(Args, Debug, Clone)]
+pub struct KeyStoreArgs {
+    #[command(flatten)]
+    pub keys: KeyArgs,
+
+    #[command(flatten)]
+    pub api: ApiArgs,
+}
+
+/// Logging options, accepted after any subcommand.
+#[derive(Args, Debug, Clone)]
+pub struct LogArgs {
+    /// Log output format. Colours are turned off when stdout is not a terminal.
+    #[arg(long, value_enum, env = "NOCKPOOL_LOG_FORMAT", default_value_t = LogFormat::Text, global = true)]
+    pub log_format: LogFormat,
+
+    /// Also write logs to this file, rotating it as configured below.
+    #[arg(long, env = "NOCKPOOL_LOG_FILE", global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// Roll the log file over `daily` or at a size such as `100MB`.
+    #[arg(long, default_value = "100MB", global = true)]
+    pub log_rotate: Rotation,
+
+    /// Number of rotated log files to keep.
+    #[arg(long, default_value_t = 7, global = true)]
+    pub log_keep: usize,
+
+    /// Gzip rotated log files.
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set, global = true)]
+    pub log_compress: bool,
+
+    /// Write logs to stdout. Set to false to log only to --log-file.
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set, global = true)]
+    pub log_stdout: bool,
+
+    /// Export mining, template and submission spans to this OTLP/gRPC
+    /// collector, e.g. `http://127.0.0.1:4317`.
+    #[arg(long, env = "OTEL_EXPORTER_OTLP_ENDPOINT", global = true)]
+    pub otlp_endpoint: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct StatusArgs {
+    /// The `ip:port` the miner's control API listens on.
+    #[arg(long, default_value = "127.0.0.1:27030")]
+    pub control_address: String,
+
+    /// Bearer token for the control API. Defaults to the one stored in the config directory.
+    #[arg(long, env = "NOCKPOOL_CONTROL_TOKEN", hide_env_values = true)]
+    pub control_token: Option<Secret>,
+
+    /// Print the raw status document as JSON.
+    #[arg(long, default_value_t = false)]
+    pub json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct DiagnoseArgs {
+    #[command(flatten)]
+    pub store: KeyStoreArgs,
+}
+
+// One way of authenticating with the pool at most. None at all is caught
+// where the key is needed, since a systemd credential or the key store may
+// still supply one.
+#[derive(Args, Debug, Clone)]
+#[command(group(
+    ArgGroup::new("credentials")
+        .args(["key", "key_file", "account_token", "account_token_file", "weighted_keys", "weighted_key_file"])
+        .multiple(false)
+))]
 pub struct Config {
     /// The secret key for authentication with the nockpool server.
     #[arg(long)]
-    pub key: Option<String>,
+    pub key: Option<Secret>,
 
-    /// Account token for generating mining tokens (alternative to --key).
-    #[arg(long, env = "NOCKPOOL_ACCOUNT_TOKEN")]
-    pub account_token: Option<String>,
+    /// Read the mining key from this file, or from stdin if `-`.
+    #[arg(long, env = "NOCKPOOL_KEY_FILE")]
+    pub key_file: Option<PathBuf>,
+
+    /// Mine for several keys at once, splitting threads by weight, as `LABEL:KEY:WEIGHT`.
+    /// May be repeated; replaces --key and --account-token. Standalone mode only.
+    #[arg(long = "weighted-key", conflicts_with_all = ["mode", "farm_proxy", "coordinator"])]
+    pub weighted_keys: Vec<WeightedKey>,
+
+    /// Read weighted keys from this file, one `LABEL:KEY:WEIGHT` per line, or from stdin if `-`.
+    #[arg(long, env = "NOCKPOOL_WEIGHTED_KEY_FILE", conflicts_with_all = ["mode", "farm_proxy", "coordinator"])]
+    pub weighted_key_file: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub keys: KeyArgs,
+
+    #[command(flatten)]
+    pub api: ApiArgs,
 
     /// Set the maximum number of threads to use for mining. Uses all available cores if not set.
     #[arg(long)]
     pub max_threads: Option<u32>,
 
-    /// The `ip:port` of the nockpool server.
-    #[arg(long, default_value = "quiver.nockpool.com:27016")]
-    pub server_address: String,
+    /// Throttle mining by local time, e.g. `mon-fri 09:00-17:00=25%; 22:00-06:00=off`.
+    #[arg(long, env = "NOCKPOOL_SCHEDULE")]
+    pub schedule: Option<Schedule>,
+
+    /// CPU package temperature in °C at which mining threads are throttled.
+    #[arg(long, env = "NOCKPOOL_THERMAL_LIMIT")]
+    pub thermal_limit: Option<f32>,
+
+    /// Degrees below --thermal-limit the CPU must cool to before threads are restored.
+    #[arg(long, default_value_t = 5.0)]
+    pub thermal_hysteresis: f32,
+
+    /// Give cores back to other processes as soon as they need them, for shared workstations.
+    #[arg(long, default_value_t = false)]
+    pub load_aware: bool,
+
+    /// Seconds the machine must stay quiet before --load-aware adds threads back.
+    #[arg(long, default_value_t = 60)]
+    pub idle_after: u64,
 
     /// The `ip:port` of the quiver client.
     #[arg(long, default_value = "0.0.0.0:27017")]
@@ -31,33 +405,221 @@ pub struct Config {
     #[arg(long, default_value_t = false)]
     pub insecure: bool,
 
-    /// Run benchmarking tool to test the performance of the miner.
+    /// `standalone` mines locally; `proxy` and `coordinator` hold the pool session for a
+    /// LAN farm; `worker` proves templates from a coordinator.
+    #[arg(long, value_enum, default_value_t = Mode::Standalone)]
+    pub mode: Mode,
+
+    /// The `ip:port` LAN miners connect to when running in proxy or coordinator mode.
+    /// Proxy mode is unauthenticated, so it only listens on localhost unless told otherwise.
+    #[arg(long, default_value = "127.0.0.1:27020")]
+    pub listen_address: String,
+
+    /// The `ip:port` of a LAN miner running in proxy mode. Mines through it instead of the pool.
+    /// Standalone mode only.
+    #[arg(long, conflicts_with_all = ["mode", "coordinator"])]
+    pub farm_proxy: Option<String>,
+
+    /// The `ip:port` of the coordinator to take work from in worker mode.
+    #[arg(long, required_if_eq("mode", "worker"))]
+    pub coordinator: Option<String>,
+
+    /// Shared secret authenticating workers and their coordinator.
+    #[arg(long, env = "NOCKPOOL_FARM_SECRET", hide_env_values = true)]
+    pub farm_secret: Option<Secret>,
+
+    /// Name reported to the LAN proxy or coordinator for share attribution. Defaults to the hostname.
+    #[arg(long)]
+    pub worker_name: Option<String>,
+
+    /// Serve the local control API (pause, resume, threads, network-only, reconnect, status).
     #[arg(long, default_value_t = false)]
-    pub benchmark: bool,
+    pub control_api: bool,
+
+    /// The `ip:port` the control API listens on.
+    #[arg(long, default_value = "127.0.0.1:27030")]
+    pub control_address: String,
+
+    /// Bearer token for the control API. Generated and stored in the config directory if not set.
+    #[arg(long, env = "NOCKPOOL_CONTROL_TOKEN", hide_env_values = true)]
+    pub control_token: Option<Secret>,
+
+    /// Serve a read-only web dashboard for checking the rig from a browser.
+    #[arg(long, default_value_t = false)]
+    pub dashboard: bool,
+
+    /// The `ip:port` the dashboard listens on.
+    #[arg(long, default_value = "0.0.0.0:27031")]
+    pub dashboard_address: String,
 
-    /// Clear stored mining key and exit.
+    /// Show a full-screen terminal dashboard instead of the log stream.
     #[arg(long, default_value_t = false)]
+    pub tui: bool,
+
+    // Deprecated spellings of the one-shot subcommands, kept so existing
+    // scripts and service files keep working.
+    /// Deprecated: use `bench`.
+    #[arg(long, default_value_t = false, hide = true)]
+    pub benchmark: bool,
+
+    /// Deprecated: use `keys clear --all`.
+    #[arg(long, default_value_t = false, hide = true)]
     pub clear_key: bool,
 
-    /// Base URL for the NockPool API (for local development).
-    #[arg(long, env = "NOCKPOOL_API_URL", default_value = "https://nockpool.com")]
-    pub api_url: String,
+    /// Deprecated: use `keys list`.
+    #[arg(long, default_value_t = false, hide = true)]
+    pub list_profiles: bool,
+
+    /// Deprecated: use `keys show --profile PROFILE`.
+    #[arg(long, value_name = "PROFILE", hide = true)]
+    pub show_profile: Option<String>,
+
+    /// Deprecated: use `keys clear --profile PROFILE`.
+    #[arg(long, value_name = "PROFILE", hide = true)]
+    pub delete_profile: Option<String>,
 }
 
-impl Config {
-    pub fn validate_auth(&self) -> Result<(), String> {
-        if self.benchmark || self.clear_key {
-            return Ok(());
+impl Command {
+    /// Reads the secrets this command was given as files, or that systemd
+    /// passed as credentials, into their options.
+    pub fn resolve_secrets(&mut self) -> anyhow::Result<()> {
+        match self {
+            Command::Run(config) => config.resolve_secrets(),
+            Command::Keys { command } => command.store_mut().keys.resolve_secrets(true),
+            Command::Unstable { command } => command.store_mut().keys.resolve_secrets(true),
+            Command::Diagnose(args) => args.store.keys.resolve_secrets(true),
+            Command::Status(args) => {
+                if args.control_token.is_none() {
+                    args.control_token = secrets::credential("control-token")?;
+                }
+                Ok(())
+            }
+            Command::Bench | Command::Config { .. } => Ok(()),
+        }
+    }
+}
+
+impl KeysCommand {
+    pub fn store(&self) -> &KeyStoreArgs {
+        match self {
+            KeysCommand::List(store)
+            | KeysCommand::Show(store)
+            | KeysCommand::Create(store)
+            | KeysCommand::Clear { store, .. } => store,
+        }
+    }
+
+    fn store_mut(&mut self) -> &mut KeyStoreArgs {
+        match self {
+            KeysCommand::List(store)
+            | KeysCommand::Show(store)
+            | KeysCommand::Create(store)
+            | KeysCommand::Clear { store, .. } => store,
+        }
+    }
+}
+
+impl DevicesCommand {
+    pub fn store(&self) -> &KeyStoreArgs {
+        match self {
+            DevicesCommand::List(store) | DevicesCommand::Rename { store, .. } | DevicesCommand::Revoke { store, .. } => store,
+        }
+    }
+
+    fn store_mut(&mut self) -> &mut KeyStoreArgs {
+        match self {
+            DevicesCommand::List(store) | DevicesCommand::Rename { store, .. } | DevicesCommand::Revoke { store, .. } => store,
+        }
+    }
+}
+
+impl UnstableCommand {
+    fn store_mut(&mut self) -> &mut KeyStoreArgs {
+        match self {
+            UnstableCommand::Devices { command } => command.store_mut(),
+            UnstableCommand::Account { command: AccountCommand::Stats(store) } | UnstableCommand::RevokeKey(store) => store,
         }
+    }
+}
+
+impl KeyArgs {
+    /// Reads `--account-token-file`, then falls back to the `account-token`
+    /// credential when `token_credential` is set, and the `key-passphrase`
+    /// credential when no passphrase is configured.
+    pub fn resolve_secrets(&mut self, token_credential: bool) -> anyhow::Result<()> {
+        if let Some(path) = &self.account_token_file {
+            self.account_token = Some(secrets::read(path)?);
+        } else if token_credential && self.account_token.is_none() {
+            self.account_token = secrets::credential("account-token")?;
+        }
+        if self.key_passphrase.is_none() && self.key_passphrase_file.is_none() {
+            self.key_passphrase = secrets::credential("key-passphrase")?;
+        }
+        Ok(())
+    }
+}
 
-        let has_key = self.key.is_some();
-        let has_account_token = self.account_token.is_some();
+impl Cli {
+    /// The subcommand to run, with the deprecated `run` flags mapped onto
+    /// theirs. Also returns the deprecated flag and its replacement, so the
+    /// caller can warn once logging is up.
+    pub fn into_command(self) -> (Command, Option<(&'static str, &'static str)>) {
+        let config = match self.command {
+            Some(command) => return (command, None),
+            None => self.run,
+        };
+        let store = || KeyStoreArgs { keys: config.keys.clone(), api: config.api.clone() };
 
-        match (has_key, has_account_token) {
-            (true, false) => Ok(()),   // Direct key provided
-            (false, true) => Ok(()),   // Account token provided
-            (false, false) => Err("Either --key or --account-token must be provided".to_string()),
-            (true, true) => Err("Cannot specify both --key and --account-token".to_string()),
+        if config.benchmark {
+            (Command::Bench, Some(("--benchmark", "bench")))
+        } else if config.clear_key {
+            let command = KeysCommand::Clear { all: true, store: store() };
+            (Command::Keys { command }, Some(("--clear-key", "keys clear --all")))
+        } else if config.list_profiles {
+            (Command::Keys { command: KeysCommand::List(store()) }, Some(("--list-profiles", "keys list")))
+        } else if let Some(profile) = &config.show_profile {
+            let mut store = store();
+            store.keys.profile = profile.clone();
+            (Command::Keys { command: KeysCommand::Show(store) }, Some(("--show-profile", "keys show --profile")))
+        } else if let Some(profile) = &config.delete_profile {
+            let mut store = store();
+            store.keys.profile = profile.clone();
+            let command = KeysCommand::Clear { all: false, store };
+            (Command::Keys { command }, Some(("--delete-profile", "keys clear --profile")))
+        } else {
+            (Command::Run(Box::new(config)), None)
+        }
+    }
+}
+
+impl Config {
+    /// Reads secrets given as files or systemd credentials. The `key`,
+    /// `weighted-keys` and `account-token` credentials are only used when no
+    /// key or account token is given any other way, so they never clash
+    /// with one that is.
+    pub fn resolve_secrets(&mut self) -> anyhow::Result<()> {
+        if let Some(path) = &self.key_file {
+            self.key = Some(secrets::read(path)?);
+        }
+        if let Some(path) = &self.weighted_key_file {
+            self.weighted_keys = read_weighted_keys(path)?;
+        }
+        let unkeyed = self.key.is_none() && self.weighted_keys.is_empty();
+        self.keys.resolve_secrets(unkeyed)?;
+        if unkeyed && self.keys.account_token.is_none() {
+            self.key = secrets::credential("key")?;
+            // Weighted keys are for standalone mining only.
+            let standalone = self.mode == Mode::Standalone && self.farm_proxy.is_none();
+            if let Some(path) = secrets::credential_path("weighted-keys").filter(|_| self.key.is_none() && standalone) {
+                self.weighted_keys = read_weighted_keys(&path)?;
+            }
+        }
+        if self.farm_secret.is_none() {
+            self.farm_secret = secrets::credential("farm-secret")?;
+        }
+        if self.control_token.is_none() {
+            self.control_token = secrets::credential("control-token")?;
         }
+        Ok(())
     }
 }
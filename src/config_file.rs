@@ -0,0 +1,309 @@
+use anyhow::{anyhow, bail, Context, Result};
+use clap::parser::ValueSource;
+use clap::error::ErrorKind;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches};
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::Cli;
+use crate::key_storage;
+
+// Options for `run` can also come from a TOML file of `long-option = value`
+// pairs. File values are spliced into the arguments ahead of the command
+// line, and only for options the command line and environment leave unset,
+// so the order of precedence is: command line, environment, file, default.
+
+const CONFIG_FILENAME: &str = "config.toml";
+
+/// Arguments whose values `config show` never prints.
+const SECRET_ARGS: &[&str] = &["key", "weighted_keys", "account_token", "key_passphrase", "farm_secret", "control_token"];
+
+const TEMPLATE: &str = r#"# nockpool-miner configuration. Every key is the long name of a `run`
+# option; the command line and environment variables take precedence.
+# Run `nockpool-miner config show` to see the values in effect.
+
+# account-token = "..."
+# profile = "default"
+# max-threads = 8
+# schedule = "mon-fri 09:00-17:00=25%; 22:00-06:00=off"
+# thermal-limit = 85.0
+# mode = "standalone"
+# worker-name = "rig-1"
+# control-api = true
+# dashboard = true
+# log-format = "json"
+# log-file = "/var/log/nockpool/miner.log"
+# tls-pin = ["spki:..."]
+"#;
+
+/// Command-line arguments merged with the config file.
+struct Loaded {
+    matches: ArgMatches,
+    path: PathBuf,
+    /// Ids of the arguments the config file supplied.
+    from_file: Vec<String>,
+}
+
+/// The config file in use: `--config` if given, otherwise the default path.
+fn config_path(explicit: Option<PathBuf>) -> Result<PathBuf> {
+    match explicit {
+        Some(path) => Ok(path),
+        None => Ok(key_storage::config_dir()?.join(CONFIG_FILENAME)),
+    }
+}
+
+/// The subcommand path taking `run` options in `matches`, and its matches.
+fn run_options(matches: &ArgMatches) -> Option<(Vec<&'static str>, &ArgMatches)> {
+    match matches.subcommand() {
+        None => Some((vec![], matches)),
+        Some(("run", run)) => Some((vec!["run"], run)),
+        Some(("config", config)) => match config.subcommand() {
+            Some(("show", show)) => Some((vec!["config", "show"], show)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The clap command for a subcommand path, with global arguments propagated.
+fn command_for(path: &[&str]) -> clap::Command {
+    let mut command = Cli::command();
+    command.build();
+    path.iter().fold(command, |command, name| {
+        command.find_subcommand(name).expect("subcommand path is valid").clone()
+    })
+}
+
+/// Renders a TOML scalar the way it would be typed on the command line.
+fn scalar(key: &str, value: &toml::Value) -> Result<String> {
+    match value {
+        toml::Value::String(value) => Ok(value.clone()),
+        toml::Value::Integer(value) => Ok(value.to_string()),
+        toml::Value::Float(value) => Ok(value.to_string()),
+        toml::Value::Boolean(value) => Ok(value.to_string()),
+        _ => bail!("'{}' must be a string, number or boolean", key),
+    }
+}
+
+/// Arguments for every option in `table` that `matches` doesn't already
+/// have from the command line or environment, plus the ids they set.
+fn file_args(table: &toml::Table, command: &clap::Command, matches: &ArgMatches) -> Result<(Vec<OsString>, Vec<String>)> {
+    let mut args = Vec::new();
+    let mut ids = Vec::new();
+    for (key, value) in table {
+        let long = key.replace('_', "-");
+        let arg = command
+            .get_arguments()
+            .filter(|arg| !arg.is_hide_set() && arg.get_id() != "config_file")
+            .find(|arg| arg.get_long() == Some(long.as_str()))
+            .ok_or_else(|| anyhow!("Unknown option '{}'", key))?;
+        let id = arg.get_id().as_str();
+        if matches!(matches.value_source(id), Some(ValueSource::CommandLine | ValueSource::EnvVariable)) {
+            continue;
+        }
+
+        let values = match value {
+            toml::Value::Array(items) => items.iter().map(|item| scalar(key, item)).collect::<Result<Vec<_>>>()?,
+            value => vec![scalar(key, value)?],
+        };
+        for value in values {
+            match arg.get_action() {
+                ArgAction::SetTrue => match value.as_str() {
+                    "true" => args.push(format!("--{}", long).into()),
+                    "false" => {}
+                    _ => bail!("'{}' must be true or false", key),
+                },
+                action if action.takes_values() => args.push(format!("--{}={}", long, value).into()),
+                _ => bail!("'{}' can't be set from the config file", key),
+            }
+        }
+        ids.push(id.to_string());
+    }
+    Ok((args, ids))
+}
+
+/// Parses the command line, then again with the config file's values
+/// spliced in after the subcommand taking `run` options.
+fn try_load(args: Vec<OsString>) -> Result<Loaded> {
+    // The file may supply an option the command line is missing, so that
+    // error waits until the file has been read.
+    let (matches, missing) = match Cli::command().try_get_matches_from(&args) {
+        Ok(matches) => (matches, None),
+        Err(e) if e.kind() == ErrorKind::MissingRequiredArgument => {
+            let matches = Cli::command().ignore_errors(true).try_get_matches_from(&args).unwrap_or_else(|_| e.exit());
+            (matches, Some(e))
+        }
+        Err(e) => e.exit(),
+    };
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let explicit = cli.config_file.is_some();
+    let path = config_path(cli.config_file)?;
+    let without_file = |matches: ArgMatches| match &missing {
+        Some(e) => e.exit(),
+        None => Ok(Loaded { matches, path: path.clone(), from_file: Vec::new() }),
+    };
+
+    let Some((subcommand, options)) = run_options(&matches) else {
+        return without_file(matches);
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && !explicit => return without_file(matches.clone()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let table: toml::Table = toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+    let (injected, from_file) = file_args(&table, &command_for(&subcommand), options)
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+
+    // Subcommand names come first, since nothing may precede them.
+    let mut insert_at = 1;
+    for name in &subcommand {
+        insert_at += args[insert_at..].iter().position(|arg| arg.to_str() == Some(*name)).map_or(0, |at| at + 1);
+    }
+    let mut merged = args[..insert_at].to_vec();
+    merged.extend(injected);
+    merged.extend_from_slice(&args[insert_at..]);
+    let matches = Cli::command().try_get_matches_from(merged).map_err(|e| {
+        if missing.is_some() && e.kind() == ErrorKind::MissingRequiredArgument {
+            e.exit();
+        }
+        let error = e.render().to_string();
+        let error = error.lines().next().unwrap_or_default().trim_start_matches("error: ").to_string();
+        anyhow!("Invalid config file {}: {}", path.display(), error)
+    })?;
+    Ok(Loaded { matches, path, from_file })
+}
+
+fn load() -> Loaded {
+    try_load(std::env::args_os().collect()).unwrap_or_else(|e| {
+        eprintln!("{:#}", e);
+        std::process::exit(2);
+    })
+}
+
+/// Parses the command line together with the config file, exiting with a
+/// usage error if either is invalid.
+pub fn parse() -> Cli {
+    Cli::from_arg_matches(&load().matches).unwrap_or_else(|e| e.exit())
+}
+
+/// `config show`: every option `run` would get and where it came from.
+pub fn show() -> Result<()> {
+    let loaded = load();
+    let (subcommand, options) = run_options(&loaded.matches).expect("`config show` takes run options");
+    println!("# {}", loaded.path.display());
+
+    for arg in command_for(&subcommand).get_arguments() {
+        let id = arg.get_id().as_str();
+        let settable = matches!(arg.get_action(), ArgAction::SetTrue) || arg.get_action().takes_values();
+        let Some(long) = arg.get_long().filter(|_| settable && !arg.is_hide_set() && id != "config_file") else {
+            continue;
+        };
+        let source = match options.value_source(id) {
+            _ if loaded.from_file.iter().any(|from_file| from_file == id) => "config file",
+            Some(ValueSource::CommandLine) => "command line",
+            Some(ValueSource::EnvVariable) => "environment",
+            Some(ValueSource::DefaultValue) => "default",
+            _ => {
+                println!("# {} =", long);
+                continue;
+            }
+        };
+        let values: Vec<String> = options
+            .get_raw(id)
+            .into_iter()
+            .flatten()
+            .map(|value| {
+                let value = value.to_string_lossy();
+                if SECRET_ARGS.contains(&id) {
+                    "\"<redacted>\"".to_string()
+                } else if value.parse::<f64>().is_ok() || value.parse::<bool>().is_ok() {
+                    value.into_owned()
+                } else {
+                    format!("{:?}", value)
+                }
+            })
+            .collect();
+        let value = match values.as_slice() {
+            [value] if !matches!(arg.get_action(), ArgAction::Append) => value.clone(),
+            values => format!("[{}]", values.join(", ")),
+        };
+        println!("{} = {}  # {}", long, value, source);
+    }
+    Ok(())
+}
+
+/// `config init`: writes a commented starting point to the config file path.
+pub fn init(explicit: Option<PathBuf>, force: bool) -> Result<PathBuf> {
+    let path = config_path(explicit)?;
+    if path.exists() && !force {
+        bail!("{} already exists; pass --force to overwrite it", path.display());
+    }
+    // Config files may hold the account token, so keep them private.
+    key_storage::write_private(&path, TEMPLATE.as_bytes())?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_with(file: &str, args: &[&str]) -> Cli {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILENAME);
+        fs::write(&path, file).unwrap();
+        let mut argv: Vec<OsString> = vec!["nockpool-miner".into()];
+        argv.extend(args.iter().map(OsString::from));
+        argv.push(format!("--config={}", path.display()).into());
+        Cli::from_arg_matches(&try_load(argv).unwrap().matches).unwrap()
+    }
+
+    #[test]
+    fn test_config_file_fills_unset_options() {
+        let file = "max-threads = 4\nworker_name = \"rig-1\"\ncontrol-api = true\ntls-pin = [\"spki:aa\", \"spki:bb\"]\n";
+
+        let cli = load_with(file, &[]);
+        assert!(cli.command.is_none());
+        assert_eq!(cli.run.max_threads, Some(4));
+        assert_eq!(cli.run.worker_name.as_deref(), Some("rig-1"));
+        assert!(cli.run.control_api);
+        assert_eq!(cli.run.api.tls_pins, vec!["spki:aa", "spki:bb"]);
+
+        // The command line wins, also after a subcommand.
+        let cli = load_with(file, &["run", "--max-threads", "2"]);
+        let Some(crate::config::Command::Run(config)) = cli.command else {
+            panic!("expected the run subcommand");
+        };
+        assert_eq!(config.max_threads, Some(2));
+        assert_eq!(config.worker_name.as_deref(), Some("rig-1"));
+    }
+
+    #[test]
+    fn test_conflicting_run_options_are_rejected_while_parsing() {
+        let parse = |args: &[&str]| Cli::command().try_get_matches_from(["nockpool-miner"].iter().chain(args)).map(|_| ());
+        let kind = |args: &[&str]| parse(args).unwrap_err().kind();
+
+        assert_eq!(kind(&["--key", "nock_a", "--account-token", "nockacct_b"]), ErrorKind::ArgumentConflict);
+        assert_eq!(kind(&["run", "--key-file", "key.txt", "--weighted-key", "a:nock_a:1"]), ErrorKind::ArgumentConflict);
+        assert_eq!(kind(&["--weighted-key", "a:nock_a:1", "--mode", "proxy"]), ErrorKind::ArgumentConflict);
+        assert_eq!(kind(&["--farm-proxy", "10.0.0.2:27020", "--mode", "coordinator"]), ErrorKind::ArgumentConflict);
+        assert_eq!(kind(&["--mode", "worker"]), ErrorKind::MissingRequiredArgument);
+        parse(&["--mode", "worker", "--coordinator", "10.0.0.2:27020", "--key", "nock_a"]).unwrap();
+        parse(&["--weighted-key", "a:nock_a:1", "--weighted-key", "b:nock_b:2"]).unwrap();
+
+        // The config file can supply what the command line leaves out.
+        let cli = load_with("coordinator = \"10.0.0.2:27020\"\n", &["--mode", "worker"]);
+        assert_eq!(cli.run.coordinator.as_deref(), Some("10.0.0.2:27020"));
+    }
+
+    #[test]
+    fn test_config_file_rejects_unknown_options() {
+        let command = command_for(&[]);
+        let matches = Cli::command().get_matches_from(["nockpool-miner"]);
+        let table: toml::Table = toml::from_str("max-thread = 4").unwrap();
+        assert!(file_args(&table, &command, &matches).is_err());
+        let table: toml::Table = toml::from_str("benchmark = true").unwrap();
+        assert!(file_args(&table, &command, &matches).is_err());
+    }
+}
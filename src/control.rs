@@ -0,0 +1,374 @@
+use anyhow::{bail, Context, Result};
+use axum::extract::{Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tracing::{error, info};
+
+use crate::config::StatusArgs;
+use crate::governor::ThreadGovernor;
+use crate::key_storage;
+use crate::pool_session::Reconnect;
+use crate::status::{MinerStatus, StatusSnapshot};
+
+// Local HTTP API for fleet tooling. Every request must carry the control
+// token as `Authorization: Bearer <token>`; when no token is configured one
+// is generated and stored next to the mining key, readable only by the user
+// running the miner.
+
+const TOKEN_FILENAME: &str = "control_token";
+
+#[derive(Clone)]
+pub struct ControlApi {
+    pub governor: Arc<ThreadGovernor>,
+    pub status: Arc<MinerStatus>,
+    pub reconnect_tx: broadcast::Sender<Reconnect>,
+}
+
+#[derive(Clone)]
+struct ApiState {
+    api: ControlApi,
+    token_digest: [u8; 32],
+}
+
+#[derive(Debug, Serialize)]
+struct ControlStatus {
+    paused: bool,
+    target_threads: u64,
+    max_threads: u64,
+    #[serde(flatten)]
+    miner: StatusSnapshot,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetThreads {
+    threads: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetNetworkOnly {
+    enabled: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiError {
+    error: String,
+}
+
+fn api_error(status: StatusCode, error: &str) -> Response {
+    (status, Json(ApiError { error: error.to_string() })).into_response()
+}
+
+/// The stored control token, if one has been generated.
+fn stored_token() -> Result<(Option<String>, PathBuf)> {
+    let path = key_storage::config_dir()?.join(TOKEN_FILENAME);
+    let token = fs::read_to_string(&path)
+        .ok()
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty());
+    Ok((token, path))
+}
+
+/// Loads the stored control token, creating one on first use.
+fn load_or_create_token() -> Result<(String, PathBuf)> {
+    let (token, path) = stored_token()?;
+    if let Some(token) = token {
+        return Ok((token, path));
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let token = hex::encode(rand::random::<[u8; 32]>());
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    std::io::Write::write_all(&mut options.open(&path)?, token.as_bytes())?;
+    Ok((token, path))
+}
+
+/// Compares digests rather than the tokens themselves so the comparison
+/// time says nothing about how much of the token matched.
+fn token_matches(expected: &[u8; 32], presented: &str) -> bool {
+    let presented: [u8; 32] = Sha256::digest(presented.as_bytes()).into();
+    expected.iter().zip(presented).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+async fn require_token(State(state): State<ApiState>, request: Request, next: Next) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| token_matches(&state.token_digest, token.trim()));
+    if !authorized {
+        return api_error(StatusCode::UNAUTHORIZED, "missing or invalid control token");
+    }
+    next.run(request).await
+}
+
+fn control_status(api: &ControlApi) -> Json<ControlStatus> {
+    Json(ControlStatus {
+        paused: api.governor.paused(),
+        target_threads: api.governor.target(),
+        max_threads: api.governor.max_threads(),
+        miner: api.status.snapshot(),
+    })
+}
+
+async fn status(State(state): State<ApiState>) -> Json<ControlStatus> {
+    control_status(&state.api)
+}
+
+async fn pause(State(state): State<ApiState>) -> Json<ControlStatus> {
+    state.api.governor.set_paused(true);
+    control_status(&state.api)
+}
+
+async fn resume(State(state): State<ApiState>) -> Json<ControlStatus> {
+    state.api.governor.set_paused(false);
+    control_status(&state.api)
+}
+
+async fn set_threads(State(state): State<ApiState>, Json(body): Json<SetThreads>) -> Json<ControlStatus> {
+    state.api.governor.set_threads(body.threads);
+    control_status(&state.api)
+}
+
+async fn set_network_only(State(state): State<ApiState>, Json(body): Json<SetNetworkOnly>) -> Json<ControlStatus> {
+    state.api.status.set_network_only(body.enabled);
+    info!("network-only mining {}", if body.enabled { "enabled" } else { "disabled" });
+    control_status(&state.api)
+}
+
+async fn reconnect(State(state): State<ApiState>) -> Response {
+    match state.api.reconnect_tx.send(Reconnect::Requested) {
+        Ok(sessions) => {
+            info!("reconnecting {} pool sessions", sessions);
+            StatusCode::ACCEPTED.into_response()
+        }
+        Err(_) => api_error(StatusCode::CONFLICT, "this miner holds no pool session"),
+    }
+}
+
+fn router(state: ApiState) -> Router {
+    Router::new()
+        .route("/status", get(status))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .route("/threads", put(set_threads))
+        .route("/network-only", put(set_network_only))
+        .route("/reconnect", post(reconnect))
+        .layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state)
+}
+
+pub async fn serve(address: String, token: Option<String>, api: ControlApi) -> Result<()> {
+    let token = match token {
+        Some(token) => token,
+        None => {
+            let (token, path) = load_or_create_token().context("failed to set up control token")?;
+            info!("control API token is stored in {}", path.display());
+            token
+        }
+    };
+    let state = ApiState {
+        api,
+        token_digest: Sha256::digest(token.as_bytes()).into(),
+    };
+
+    let listener = tokio::net::TcpListener::bind(&address)
+        .await
+        .with_context(|| format!("failed to bind control API on {}", address))?;
+    info!("control API listening on {}", address);
+    axum::serve(listener, router(state)).await?;
+    Ok(())
+}
+
+pub fn spawn(address: String, token: Option<String>, api: ControlApi) {
+    tokio::spawn(async move {
+        if let Err(e) = serve(address, token, api).await {
+            error!("control API stopped: {:#}", e);
+        }
+    });
+}
+
+/// `status`: asks a running miner's control API how it is doing.
+pub async fn print_status(args: &StatusArgs) -> Result<()> {
+    let token = match &args.control_token {
+        Some(token) => token.expose().to_string(),
+        None => match stored_token()? {
+            (Some(token), _) => token,
+            (None, path) => bail!("No control token at {}; pass --control-token", path.display()),
+        },
+    };
+    let url = format!("http://{}/status", args.control_address);
+    let response = reqwest::Client::new()
+        .get(&url)
+        .bearer_auth(token)
+        .send()
+        .await
+        .with_context(|| format!("failed to reach the control API at {}; is the miner running with --control-api?", args.control_address))?;
+    if !response.status().is_success() {
+        bail!("control API returned {}", response.status());
+    }
+    let status: Value = response.json().await.context("invalid status response")?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&status)?);
+        return Ok(());
+    }
+    let number = |field: &str| status[field].as_f64().unwrap_or_default();
+    println!("state:      {}", if status["paused"].as_bool() == Some(true) { "paused" } else { "mining" });
+    println!("threads:    {} of {}", number("target_threads"), number("max_threads"));
+    println!("uptime:     {}s", number("uptime_secs"));
+    println!("proofs/min: {:.1}", number("proofs_per_minute"));
+    println!("hits:       {} pool, {} network", number("pool_hits"), number("network_hits"));
+    match status["template_age_secs"].as_u64() {
+        Some(age) => println!("template:   {}s old", age),
+        None => println!("template:   none yet"),
+    }
+    for connection in status["connections"].as_array().into_iter().flatten() {
+        println!(
+            "connection: {} {} for {}s",
+            connection["name"].as_str().unwrap_or("?"),
+            connection["state"].as_str().unwrap_or("?"),
+            connection["since_secs"]
+        );
+    }
+    for session in status["sessions"].as_array().into_iter().flatten() {
+        println!(
+            "shares:     {}{} found, {} accepted, {} rejected",
+            session["label"].as_str().map(|label| format!("{}: ", label)).unwrap_or_default(),
+            session["found"],
+            session["accepted"],
+            session["rejected"]
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Method;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "s3cret";
+
+    fn control_api() -> ControlApi {
+        ControlApi {
+            governor: ThreadGovernor::new(8),
+            status: MinerStatus::new(false),
+            reconnect_tx: broadcast::channel(4).0,
+        }
+    }
+
+    fn control_router(api: &ControlApi) -> Router {
+        router(ApiState {
+            api: api.clone(),
+            token_digest: Sha256::digest(TOKEN.as_bytes()).into(),
+        })
+    }
+
+    /// Sends one request, returning its status and JSON body (null when empty).
+    async fn call(router: &Router, method: Method, uri: &str, token: Option<&str>, body: Option<Value>) -> (StatusCode, Value) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = match body {
+            Some(body) => request.header(header::CONTENT_TYPE, "application/json").body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        };
+        let response = router.clone().oneshot(request.unwrap()).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_routes_act_for_the_token_holder() {
+        let api = control_api();
+        let router = control_router(&api);
+
+        let (status, body) = call(&router, Method::GET, "/status", Some(TOKEN), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["paused"], false);
+        assert_eq!((body["target_threads"].clone(), body["max_threads"].clone()), (json!(8), json!(8)));
+
+        let (status, body) = call(&router, Method::POST, "/pause", Some(TOKEN), None).await;
+        assert_eq!((status, body["paused"].clone(), body["target_threads"].clone()), (StatusCode::OK, json!(true), json!(0)));
+        assert!(api.governor.paused());
+
+        let (status, body) = call(&router, Method::POST, "/resume", Some(TOKEN), None).await;
+        assert_eq!((status, body["paused"].clone()), (StatusCode::OK, json!(false)));
+        assert!(!api.governor.paused());
+
+        let (status, body) = call(&router, Method::PUT, "/threads", Some(TOKEN), Some(json!({"threads": 3}))).await;
+        assert_eq!((status, body["target_threads"].clone()), (StatusCode::OK, json!(3)));
+        assert_eq!(api.governor.target(), 3);
+
+        let (status, body) = call(&router, Method::PUT, "/network-only", Some(TOKEN), Some(json!({"enabled": true}))).await;
+        assert_eq!((status, body["network_only"].clone()), (StatusCode::OK, json!(true)));
+        assert!(api.status.network_only());
+
+        // Without a pool session there is nothing to reconnect.
+        let (status, _) = call(&router, Method::POST, "/reconnect", Some(TOKEN), None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let mut reconnect_rx = api.reconnect_tx.subscribe();
+        let (status, _) = call(&router, Method::POST, "/reconnect", Some(TOKEN), None).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert!(matches!(reconnect_rx.try_recv(), Ok(Reconnect::Requested)));
+    }
+
+    #[tokio::test]
+    async fn test_routes_refuse_a_missing_or_wrong_token() {
+        let api = control_api();
+        let router = control_router(&api);
+        let mut reconnect_rx = api.reconnect_tx.subscribe();
+
+        let requests = [
+            (Method::GET, "/status", None, None),
+            (Method::POST, "/pause", None, Some("wrong")),
+            (Method::POST, "/resume", Some(json!({})), None),
+            (Method::PUT, "/threads", Some(json!({"threads": 3})), Some("wrong")),
+            (Method::PUT, "/network-only", Some(json!({"enabled": true})), None),
+            (Method::POST, "/reconnect", None, Some("wrong")),
+        ];
+        for (method, uri, body, token) in requests {
+            let (status, body) = call(&router, method, uri, token, body).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", uri);
+            assert_eq!(body["error"], "missing or invalid control token");
+        }
+
+        // Nothing was changed on the way.
+        assert!(!api.governor.paused());
+        assert_eq!(api.governor.target(), 8);
+        assert!(!api.status.network_only());
+        assert!(reconnect_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_token_matches() {
+        let digest: [u8; 32] = Sha256::digest(b"s3cret").into();
+        assert!(token_matches(&digest, "s3cret"));
+        assert!(!token_matches(&digest, "s3cret "));
+        assert!(!token_matches(&digest, ""));
+    }
+}
@@ -0,0 +1,189 @@
+use anyhow::{Context, Result};
+use axum::extract::State;
+use axum::response::Html;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Serialize;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use quiver::device_info::DeviceInfo;
+
+use crate::governor::ThreadGovernor;
+use crate::status::{MinerStatus, StatusSnapshot};
+use crate::tracer::{self, LogLine};
+
+// Read-only web dashboard for farm staff. The page is a single embedded
+// HTML file that polls `/api/dashboard` every few seconds; nothing on it can
+// change what the miner does, that is what the control API is for.
+
+const DASHBOARD_HTML: &str = include_str!("dashboard.html");
+
+#[derive(Clone)]
+pub struct Dashboard {
+    pub governor: Arc<ThreadGovernor>,
+    pub status: Arc<MinerStatus>,
+    pub device_info: DeviceInfo,
+    pub worker: String,
+}
+
+#[derive(Debug, Serialize)]
+struct DeviceSnapshot {
+    os: String,
+    cpu_model: String,
+    ram_capacity_gb: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct DashboardSnapshot {
+    worker: String,
+    version: &'static str,
+    device: DeviceSnapshot,
+    paused: bool,
+    target_threads: u64,
+    max_threads: u64,
+    #[serde(flatten)]
+    miner: StatusSnapshot,
+    logs: Vec<LogLine>,
+}
+
+async fn page() -> Html<&'static str> {
+    Html(DASHBOARD_HTML)
+}
+
+async fn snapshot(State(dashboard): State<Dashboard>) -> Json<DashboardSnapshot> {
+    Json(DashboardSnapshot {
+        worker: dashboard.worker.clone(),
+        version: env!("CARGO_PKG_VERSION"),
+        device: DeviceSnapshot {
+            os: dashboard.device_info.os.clone(),
+            cpu_model: dashboard.device_info.cpu_model.clone(),
+            ram_capacity_gb: dashboard.device_info.ram_capacity_gb,
+        },
+        paused: dashboard.governor.paused(),
+        target_threads: dashboard.governor.target(),
+        max_threads: dashboard.governor.max_threads(),
+        miner: dashboard.status.snapshot(),
+        logs: tracer::recent_lines(),
+    })
+}
+
+fn router(dashboard: Dashboard) -> Router {
+    Router::new()
+        .route("/", get(page))
+        .route("/api/dashboard", get(snapshot))
+        .with_state(dashboard)
+}
+
+pub async fn serve(address: String, dashboard: Dashboard) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(&address)
+        .await
+        .with_context(|| format!("failed to bind dashboard on {}", address))?;
+    info!("dashboard available at http://{}", address);
+    axum::serve(listener, router(dashboard)).await?;
+    Ok(())
+}
+
+pub fn spawn(address: String, dashboard: Dashboard) {
+    tokio::spawn(async move {
+        if let Err(e) = serve(address, dashboard).await {
+            error!("dashboard stopped: {:#}", e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use bytes::Bytes;
+    use quiver::types::{Submission, Target, Template};
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    use crate::share_log::{ShareContext, ShareLog};
+    use crate::stats::ShareStats;
+    use crate::status::{AttemptResult, ConnectionState};
+
+    /// Every field `dashboard.html` reads, as JSON pointers.
+    const PAGE_FIELDS: &[&str] = &[
+        "/worker",
+        "/version",
+        "/paused",
+        "/target_threads",
+        "/max_threads",
+        "/proofs_per_minute",
+        "/template_age_secs",
+        "/pool_hits",
+        "/network_hits",
+        "/uptime_secs",
+        "/device/os",
+        "/device/cpu_model",
+        "/device/ram_capacity_gb",
+        "/sessions/0/label",
+        "/sessions/0/found",
+        "/sessions/0/accepted",
+        "/sessions/0/rejected",
+        "/last_submission/accepted",
+        "/last_submission/reason",
+        "/last_submission/at",
+        "/connections/0/name",
+        "/connections/0/state",
+        "/connections/0/retry_in_ms",
+        "/connections/0/since_secs",
+        "/threads/0/id",
+        "/threads/0/session",
+        "/threads/0/attempts",
+        "/threads/0/attempt_secs",
+        "/threads/0/last_attempt_secs",
+        "/threads/0/last_result",
+        "/logs",
+    ];
+
+    #[tokio::test]
+    async fn test_snapshot_has_every_field_the_page_reads() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let share_log = ShareLog::with_path(temp_dir.path().join("rejected_shares.jsonl"));
+        let status = MinerStatus::new(false);
+        let session = "ops".to_string();
+        status.add_session(Some(session.clone()), Arc::new(ShareStats::default()), &share_log);
+        status.template_received();
+        status.set_connection("pool", ConnectionState::Backoff { retry_in_ms: 500 });
+        status.attempt_started(0, Some(&session));
+        status.attempt_finished(0, AttemptResult::Miss);
+
+        let template = Template::new(Bytes::new(), Bytes::new(), Bytes::new(), Bytes::new(), Bytes::new());
+        let submission = Submission::new(Target::Pool, Bytes::new(), Bytes::from_static(&[0xbb]), Bytes::new());
+        share_log.record_found(ShareContext::new(0, &template, &submission));
+        share_log.mark_submitted(&submission);
+        share_log.record_outcome(Some("stale block".to_string()));
+        // The verdict reaches the status on a task of its own.
+        while status.snapshot().last_submission.is_none() {
+            tokio::task::yield_now().await;
+        }
+
+        let dashboard = Dashboard {
+            governor: ThreadGovernor::new(4),
+            status,
+            device_info: DeviceInfo {
+                os: "Linux".to_string(),
+                cpu_model: "Test CPU".to_string(),
+                ram_capacity_gb: 16,
+            },
+            worker: "rig-1".to_string(),
+        };
+        let request = Request::get("/api/dashboard").body(Body::empty()).unwrap();
+        let response = router(dashboard).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let snapshot: Value = serde_json::from_slice(&body).unwrap();
+
+        for field in PAGE_FIELDS {
+            assert!(snapshot.pointer(field).is_some(), "dashboard.html reads {} but the snapshot has no such field", field);
+        }
+        assert_eq!(snapshot["last_submission"]["accepted"], false);
+        assert_eq!(snapshot["connections"][0]["state"], "backoff");
+        assert_eq!(snapshot["threads"][0]["last_result"], "miss");
+    }
+}
@@ -15,4 +15,16 @@ pub fn get_device_info() -> DeviceInfo {
         cpu_model: cpu_model.clone().trim().to_string(),
         ram_capacity_gb,
     }
-}
\ No newline at end of file
+}
+/// Best-effort hostname lookup used for device nicknames and worker names.
+pub fn hostname() -> Option<String> {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .or_else(|_| {
+            // Try reading from /etc/hostname on Unix systems
+            std::fs::read_to_string("/etc/hostname")
+                .map(|s| s.trim().to_string())
+        })
+        .ok()
+        .filter(|hostname| !hostname.is_empty())
+}
@@ -0,0 +1,110 @@
+use anyhow::{bail, Result};
+use chrono::Utc;
+use std::time::Duration;
+use sysinfo::Components;
+
+use crate::config::DiagnoseArgs;
+use crate::key_manager::KeyManager;
+use crate::key_storage;
+use crate::net::{self, HttpOptions};
+use crate::thermal;
+
+// `diagnose`: the checks worth running before asking for help. Each prints
+// one line; the command fails if any check does, so it can gate a deploy.
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+enum Check {
+    Ok(String),
+    Warn(String),
+    Fail(String),
+}
+
+fn report(name: &str, check: Check) -> bool {
+    let (tag, detail, passed) = match check {
+        Check::Ok(detail) => ("ok", detail, true),
+        Check::Warn(detail) => ("warn", detail, true),
+        Check::Fail(detail) => ("FAIL", detail, false),
+    };
+    println!("[{:<4}] {:<14} {}", tag, name, detail);
+    passed
+}
+
+fn check_config_dir() -> Check {
+    let dir = match key_storage::config_dir() {
+        Ok(dir) => dir,
+        Err(e) => return Check::Fail(format!("{:#}", e)),
+    };
+    let writable = std::fs::create_dir_all(&dir).and_then(|_| tempfile::tempfile_in(&dir));
+    match writable {
+        Ok(_) => Check::Ok(format!("{} is writable", dir.display())),
+        Err(e) => Check::Fail(format!("{} is not writable: {}", dir.display(), e)),
+    }
+}
+
+fn check_stored_key(key_manager: &KeyManager, args: &DiagnoseArgs) -> Check {
+    let profile = &args.store.keys.profile;
+    match key_manager.stored_key() {
+        Ok(Some(stored)) => match stored.expires_at {
+            Some(expires_at) if expires_at <= Utc::now() => {
+                Check::Fail(format!("{} in profile {} expired on {}", key_storage::redact(&stored.key), profile, expires_at.format("%Y-%m-%d")))
+            }
+            _ => Check::Ok(format!("{} in profile {}, created {}", key_storage::redact(&stored.key), profile, stored.created_at.format("%Y-%m-%d"))),
+        },
+        Ok(None) if key_manager.has_legacy_key() => {
+            Check::Ok(format!("legacy key present, will migrate to profile {} on the next run", key_storage::DEFAULT_PROFILE))
+        }
+        Ok(None) if args.store.keys.account_token.is_some() => {
+            Check::Ok(format!("none in profile {}; one will be created with the account token", profile))
+        }
+        Ok(None) => Check::Warn(format!("none in profile {}; `run` needs --key or --account-token", profile)),
+        Err(e) => Check::Fail(format!("{:#}", e)),
+    }
+}
+
+async fn check_api(args: &DiagnoseArgs) -> Check {
+    let api_url = &args.store.api.api_url;
+    let client = match HttpOptions::from_config(&args.store.api).and_then(|options| net::build_http_client(&options)) {
+        Ok(client) => client,
+        Err(e) => return Check::Fail(format!("{:#}", e)),
+    };
+    match client.get(api_url).timeout(CHECK_TIMEOUT).send().await {
+        Ok(response) => Check::Ok(format!("{} answered {}", api_url, response.status())),
+        Err(e) => Check::Fail(format!("{} is unreachable: {}", api_url, e)),
+    }
+}
+
+async fn check_pool(args: &DiagnoseArgs) -> Check {
+    let server_address = &args.store.api.server_address;
+    match tokio::time::timeout(CHECK_TIMEOUT, tokio::net::lookup_host(server_address)).await {
+        Ok(Ok(mut addresses)) => match addresses.next() {
+            Some(address) => Check::Ok(format!("{} resolves to {}", server_address, address.ip())),
+            None => Check::Fail(format!("{} has no addresses", server_address)),
+        },
+        Ok(Err(e)) => Check::Fail(format!("{} does not resolve: {}", server_address, e)),
+        Err(_) => Check::Fail(format!("{} did not resolve within {}s", server_address, CHECK_TIMEOUT.as_secs())),
+    }
+}
+
+fn check_temperature() -> Check {
+    match thermal::cpu_temperature(&Components::new_with_refreshed_list()) {
+        Some(temperature) => Check::Ok(format!("CPU at {:.0}°C", temperature)),
+        None => Check::Warn("no CPU temperature sensor found; --thermal-limit won't work".to_string()),
+    }
+}
+
+pub async fn run(args: &DiagnoseArgs) -> Result<()> {
+    let mut passed = report("config dir", check_config_dir());
+    passed &= match KeyManager::new(&args.store.keys, &args.store.api, true) {
+        Ok(key_manager) => report("stored key", check_stored_key(&key_manager, args)),
+        Err(e) => report("stored key", Check::Fail(format!("{:#}", e))),
+    };
+    passed &= report("api", check_api(args).await);
+    passed &= report("pool server", check_pool(args).await);
+    passed &= report("temperature", check_temperature());
+
+    if !passed {
+        bail!("Some checks failed");
+    }
+    Ok(())
+}
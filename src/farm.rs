@@ -0,0 +1,653 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc, watch};
+use tracing::{error, info, info_span, warn, Instrument, Span};
+
+use quiver::types::{Submission, Target, Template};
+
+use crate::miner::NonceRange;
+use crate::secure_channel::{self, FarmKey, Role};
+use crate::share_log::{ShareContext, ShareLog};
+use crate::status::{ConnectionState, MinerStatus};
+use crate::submission;
+
+// LAN farm support. One nockpool-miner in proxy or coordinator mode holds
+// the upstream quiver session and fans templates out to LAN miners over a
+// small length-prefixed bincode protocol; their shares come back over the
+// same connection and are queued for the upstream session, tagged with the
+// worker that found them. Coordinators additionally require the encrypted
+// transport in `secure_channel` and hand each worker its own nonce range.
+
+/// Largest frame we accept; proofs are a few hundred KB.
+pub(crate) const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+/// Number of disjoint nonce ranges a coordinator hands out.
+const NONCE_PARTITIONS: u64 = 1 << 16;
+
+/// How long a peer gets to complete the encrypted handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WireTemplate {
+    pub version: Vec<u8>,
+    pub commit: Vec<u8>,
+    pub network_target: Vec<u8>,
+    pub pool_target: Vec<u8>,
+    pub pow_len: Vec<u8>,
+}
+
+impl From<&Template> for WireTemplate {
+    fn from(template: &Template) -> Self {
+        Self {
+            version: template.version.to_vec(),
+            commit: template.commit.to_vec(),
+            network_target: template.network_target.to_vec(),
+            pool_target: template.pool_target.to_vec(),
+            pow_len: template.pow_len.to_vec(),
+        }
+    }
+}
+
+impl From<WireTemplate> for Template {
+    fn from(template: WireTemplate) -> Self {
+        Template::new(
+            Bytes::from(template.version),
+            Bytes::from(template.commit),
+            Bytes::from(template.network_target),
+            Bytes::from(template.pool_target),
+            Bytes::from(template.pow_len),
+        )
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum WireTarget {
+    Pool,
+    Network,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WireSubmission {
+    pub target: WireTarget,
+    pub commit: Vec<u8>,
+    pub digest: Vec<u8>,
+    pub proof: Vec<u8>,
+    pub share: ShareContext,
+}
+
+impl WireSubmission {
+    pub fn new(submission: &Submission, share: ShareContext) -> Self {
+        Self {
+            target: match submission.target {
+                Target::Pool => WireTarget::Pool,
+                Target::Network => WireTarget::Network,
+            },
+            commit: submission.commit.to_vec(),
+            digest: submission.digest.to_vec(),
+            proof: submission.proof.to_vec(),
+            share,
+        }
+    }
+
+    fn into_submission(self) -> (Submission, ShareContext) {
+        let target = match self.target {
+            WireTarget::Pool => Target::Pool,
+            WireTarget::Network => Target::Network,
+        };
+        let submission = Submission::new(
+            target,
+            Bytes::from(self.commit),
+            Bytes::from(self.digest),
+            Bytes::from(self.proof),
+        );
+        (submission, self.share)
+    }
+}
+
+/// Messages sent by LAN miners to the proxy or coordinator.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum MinerMessage {
+    Hello { worker: String, threads: u64, version: String },
+    Submit(Box<WireSubmission>),
+}
+
+/// Messages sent by the proxy or coordinator to LAN miners.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ProxyMessage {
+    Template(WireTemplate),
+    /// Nonce range this miner should search; only sent by coordinators.
+    Assign(NonceRange),
+    /// Pool verdict for the oldest share this miner submitted.
+    Verdict { rejection: Option<String> },
+}
+
+/// Outgoing half of a farm connection, plain or encrypted.
+#[async_trait]
+pub trait FrameSink: Send {
+    async fn send(&mut self, payload: &[u8]) -> Result<()>;
+}
+
+/// Incoming half of a farm connection, plain or encrypted.
+#[async_trait]
+pub trait FrameSource: Send {
+    async fn recv(&mut self) -> Result<Vec<u8>>;
+}
+
+#[async_trait]
+impl FrameSink for OwnedWriteHalf {
+    async fn send(&mut self, payload: &[u8]) -> Result<()> {
+        self.write_u32(payload.len() as u32).await?;
+        self.write_all(payload).await?;
+        self.flush().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FrameSource for OwnedReadHalf {
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        let len = self.read_u32().await? as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(anyhow!("frame of {} bytes exceeds limit", len));
+        }
+        let mut payload = vec![0; len];
+        self.read_exact(&mut payload).await?;
+        Ok(payload)
+    }
+}
+
+type Transport = (Box<dyn FrameSource>, Box<dyn FrameSink>);
+
+/// Plain framing without a farm secret, the encrypted channel with one.
+async fn open_transport(stream: TcpStream, farm_key: Option<&FarmKey>, role: Role) -> Result<Transport> {
+    stream.set_nodelay(true)?;
+    match farm_key {
+        Some(farm_key) => {
+            let (reader, writer) = tokio::time::timeout(HANDSHAKE_TIMEOUT, secure_channel::handshake(stream, farm_key, role))
+                .await
+                .map_err(|_| anyhow!("handshake timed out"))??;
+            Ok((Box::new(reader), Box::new(writer)))
+        }
+        None => {
+            let (reader, writer) = stream.into_split();
+            Ok((Box::new(reader), Box::new(writer)))
+        }
+    }
+}
+
+async fn write_frame<T: Serialize>(sink: &mut dyn FrameSink, message: &T) -> Result<()> {
+    sink.send(&bincode::serialize(message)?).await
+}
+
+/// Reads frames on a dedicated task so the caller can `select!` on a channel;
+/// reading a frame is not cancellation safe.
+fn spawn_reader<T>(mut source: Box<dyn FrameSource>) -> mpsc::Receiver<Result<T>>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(16);
+    tokio::spawn(async move {
+        loop {
+            let frame = source
+                .recv()
+                .await
+                .and_then(|payload| bincode::deserialize(&payload).map_err(Into::into));
+            let failed = frame.is_err();
+            if tx.send(frame).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+fn has_template(template: &Template) -> bool {
+    !template.commit.is_empty()
+}
+
+/// Proxy/coordinator side: accept LAN miners, push them templates from the
+/// upstream session and queue their shares for submission upstream. With a
+/// farm key every connection must complete the encrypted handshake.
+pub async fn serve(
+    listen_address: String,
+    farm_key: Option<FarmKey>,
+    template_rx: watch::Receiver<Template>,
+    submission_tx: mpsc::Sender<Submission>,
+    share_log: Arc<ShareLog>,
+) -> Result<()> {
+    let listener = TcpListener::bind(&listen_address).await?;
+    if farm_key.is_some() {
+        info!("coordinator listening for workers on {}", listen_address);
+    } else {
+        info!("proxy listening for LAN miners on {}", listen_address);
+    }
+    serve_listener(listener, farm_key, template_rx, submission_tx, share_log).await
+}
+
+async fn serve_listener(
+    listener: TcpListener,
+    farm_key: Option<FarmKey>,
+    template_rx: watch::Receiver<Template>,
+    submission_tx: mpsc::Sender<Submission>,
+    share_log: Arc<ShareLog>,
+) -> Result<()> {
+    let farm_key = farm_key.map(Arc::new);
+    let total_threads = Arc::new(AtomicU64::new(0));
+
+    let next_connection = AtomicU64::new(0);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let connection = next_connection.fetch_add(1, Ordering::Relaxed);
+        let template_rx = template_rx.clone();
+        let submission_tx = submission_tx.clone();
+        let share_log = share_log.clone();
+        let farm_key = farm_key.clone();
+        let total_threads = total_threads.clone();
+
+        tokio::spawn(async move {
+            let transport = match open_transport(stream, farm_key.as_deref(), Role::Coordinator).await {
+                Ok(transport) => transport,
+                Err(e) => {
+                    warn!("rejected connection from {}: {}", peer, e);
+                    return;
+                }
+            };
+            // Coordinators split the nonce space between workers.
+            let nonce_range = farm_key
+                .is_some()
+                .then(|| NonceRange::partition(connection, NONCE_PARTITIONS));
+            let served = serve_miner(transport, peer, connection, nonce_range, &total_threads, template_rx, submission_tx, share_log).await;
+            if let Err(e) = served {
+                warn!("LAN miner {} disconnected: {}", peer, e);
+            }
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn serve_miner(
+    (source, mut sink): Transport,
+    peer: std::net::SocketAddr,
+    connection: u64,
+    nonce_range: Option<NonceRange>,
+    total_threads: &AtomicU64,
+    mut template_rx: watch::Receiver<Template>,
+    submission_tx: mpsc::Sender<Submission>,
+    share_log: Arc<ShareLog>,
+) -> Result<()> {
+    let mut frames = spawn_reader::<MinerMessage>(source);
+
+    let (worker, threads) = match frames.recv().await {
+        Some(Ok(MinerMessage::Hello { worker, threads, version })) => {
+            info!("LAN miner worker={} connected from {} with {} threads (v{})", worker, peer, threads, version);
+            (worker, threads)
+        }
+        Some(Ok(_)) => return Err(anyhow!("expected hello as the first message")),
+        Some(Err(e)) => return Err(e),
+        None => return Err(anyhow!("connection closed before hello")),
+    };
+
+    let farm_threads = total_threads.fetch_add(threads, Ordering::Relaxed) + threads;
+    info!("{} threads connected in total", farm_threads);
+    let result = serve_registered(&mut frames, sink.as_mut(), &worker, connection, nonce_range, &mut template_rx, &submission_tx, &share_log).await;
+    let farm_threads = total_threads.fetch_sub(threads, Ordering::Relaxed) - threads;
+    info!("{} threads connected in total", farm_threads);
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn serve_registered(
+    frames: &mut mpsc::Receiver<Result<MinerMessage>>,
+    writer: &mut dyn FrameSink,
+    worker: &str,
+    connection: u64,
+    nonce_range: Option<NonceRange>,
+    template_rx: &mut watch::Receiver<Template>,
+    submission_tx: &mpsc::Sender<Submission>,
+    share_log: &ShareLog,
+) -> Result<()> {
+    let mut outcomes = share_log.subscribe();
+
+    if let Some(nonce_range) = nonce_range {
+        write_frame(writer, &ProxyMessage::Assign(nonce_range)).await?;
+    }
+
+    let current = template_rx.borrow_and_update().clone();
+    if has_template(&current) {
+        write_frame(writer, &ProxyMessage::Template(WireTemplate::from(&current))).await?;
+    }
+
+    let (mut submitted, mut accepted, mut rejected) = (0u64, 0u64, 0u64);
+    let result = loop {
+        tokio::select! {
+            frame = frames.recv() => {
+                let message = match frame {
+                    Some(Ok(message)) => message,
+                    Some(Err(e)) => break Err(e),
+                    None => break Ok(()),
+                };
+                match message {
+                    MinerMessage::Submit(wire) => {
+                        let (submission, mut share) = wire.into_submission();
+                        share.worker = Some(worker.to_string());
+                        share.connection = Some(connection);
+                        info!(
+                            "share from worker={} thread={} for target={}, forwarding upstream",
+                            worker, share.thread_id, share.target
+                        );
+                        share_log.record_found(share);
+                        submitted += 1;
+                        match submission_tx.try_send(submission) {
+                            Ok(()) => {}
+                            Err(mpsc::error::TrySendError::Full(_)) => {
+                                // The worker still waits for a verdict on every share it sent.
+                                rejected += 1;
+                                warn!("upstream submission queue full, dropping share from worker={}", worker);
+                                let rejection = Some("proxy submission queue full".to_string());
+                                if let Err(e) = write_frame(writer, &ProxyMessage::Verdict { rejection }).await {
+                                    break Err(e);
+                                }
+                            }
+                            Err(mpsc::error::TrySendError::Closed(_)) => break Err(anyhow!("upstream submission queue closed")),
+                        }
+                    }
+                    MinerMessage::Hello { .. } => warn!("ignoring repeated hello from worker={}", worker),
+                }
+            }
+            outcome = outcomes.recv() => {
+                let outcome = match outcome {
+                    Ok(outcome) => outcome,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("worker={} missed {} share verdicts", worker, skipped);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break Ok(()),
+                };
+                if outcome.share.connection != Some(connection) {
+                    continue;
+                }
+                match &outcome.rejection {
+                    None => accepted += 1,
+                    Some(reason) => {
+                        rejected += 1;
+                        warn!("pool rejected share from worker={}: {}", worker, reason);
+                    }
+                }
+                if let Err(e) = write_frame(writer, &ProxyMessage::Verdict { rejection: outcome.rejection }).await {
+                    break Err(e);
+                }
+            }
+            changed = template_rx.changed() => {
+                if changed.is_err() {
+                    break Ok(());
+                }
+                let template = template_rx.borrow_and_update().clone();
+                if let Err(e) = write_frame(writer, &ProxyMessage::Template(WireTemplate::from(&template))).await {
+                    break Err(e);
+                }
+            }
+        }
+    };
+
+    info!(
+        "LAN miner worker={} disconnected: {} shares submitted, {} accepted, {} rejected",
+        worker, submitted, accepted, rejected
+    );
+    result
+}
+
+/// Miner side: keep a connection to a LAN proxy or coordinator open,
+/// feeding its templates to the local miner and relaying found shares back.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_client(
+    proxy_address: String,
+    farm_key: Option<FarmKey>,
+    worker: String,
+    threads: u64,
+    template_tx: watch::Sender<Template>,
+    nonce_range_tx: watch::Sender<NonceRange>,
+    mut submission_rx: mpsc::Receiver<Submission>,
+    share_log: Arc<ShareLog>,
+    status: Arc<MinerStatus>,
+) {
+    let mut backoff_ms = 100_u64;
+    let max_backoff_ms = 30_000_u64;
+
+    loop {
+        status.set_connection("farm", ConnectionState::Connecting);
+        let connected = connect_client(
+            &proxy_address,
+            farm_key.as_ref(),
+            &worker,
+            threads,
+            &template_tx,
+            &nonce_range_tx,
+            &mut submission_rx,
+            &share_log,
+            &status,
+        )
+        .await;
+        match connected {
+            Ok(()) => {
+                info!("Farm connection closed, reconnecting immediately");
+                backoff_ms = 100;
+            }
+            Err(e) => {
+                error!("Farm connection to {} failed: {}", proxy_address, e);
+                info!("Sleeping for {}ms before reconnecting", backoff_ms);
+                status.set_connection("farm", ConnectionState::Backoff { retry_in_ms: backoff_ms });
+                tokio::time::sleep(tokio::time::Duration::from_millis(backoff_ms)).await;
+                backoff_ms = (backoff_ms * 2).min(max_backoff_ms);
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn connect_client(
+    proxy_address: &str,
+    farm_key: Option<&FarmKey>,
+    worker: &str,
+    threads: u64,
+    template_tx: &watch::Sender<Template>,
+    nonce_range_tx: &watch::Sender<NonceRange>,
+    submission_rx: &mut mpsc::Receiver<Submission>,
+    share_log: &ShareLog,
+    status: &MinerStatus,
+) -> Result<()> {
+    let stream = TcpStream::connect(proxy_address).await?;
+    let (source, mut writer) = open_transport(stream, farm_key, Role::Worker).await?;
+    status.set_connection("farm", ConnectionState::Connected);
+    // Verdicts are routed per connection, so nothing sent on an earlier one
+    // will be answered, and shares queued meanwhile were found on templates
+    // the proxy is about to replace.
+    share_log.abandon_in_flight();
+    submission::discard_queued(submission_rx);
+    // Until a coordinator says otherwise, search the whole nonce space.
+    let _ = nonce_range_tx.send(NonceRange::FULL);
+    let mut frames = spawn_reader::<ProxyMessage>(source);
+
+    write_frame(
+        writer.as_mut(),
+        &MinerMessage::Hello {
+            worker: worker.to_string(),
+            threads,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        },
+    )
+    .await?;
+    info!("connected to {} as worker={}", proxy_address, worker);
+
+    loop {
+        tokio::select! {
+            frame = frames.recv() => {
+                match frame {
+                    Some(Ok(ProxyMessage::Template(template))) => {
+                        let _ = template_tx.send(Template::from(template));
+                    }
+                    Some(Ok(ProxyMessage::Assign(nonce_range))) => {
+                        info!("coordinator assigned nonce range starting at {}", nonce_range.start);
+                        let _ = nonce_range_tx.send(nonce_range);
+                    }
+                    Some(Ok(ProxyMessage::Verdict { rejection })) => {
+                        share_log.record_outcome(rejection);
+                    }
+                    Some(Err(e)) => return Err(e),
+                    None => return Ok(()),
+                }
+            }
+            submission = submission_rx.recv() => {
+                let Some(submission) = submission else {
+                    return Ok(());
+                };
+                let Some(share) = share_log.mark_submitted(&submission) else {
+                    warn!("dropping share with no recorded context");
+                    continue;
+                };
+                let parent = share.span.clone().unwrap_or_else(Span::none);
+                let span = info_span!(parent: &parent, "submission_send", digest = %share.digest, target = %share.target);
+                write_frame(writer.as_mut(), &MinerMessage::Submit(Box::new(WireSubmission::new(&submission, share))))
+                    .instrument(span)
+                    .await?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::submission::SUBMISSION_QUEUE;
+    use tokio::time::timeout;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    fn template(commit: u8) -> Template {
+        let field = |value: &'static [u8]| Bytes::from_static(value);
+        Template::new(field(b"v"), Bytes::from(vec![commit]), field(b"n"), field(b"p"), field(b"l"))
+    }
+
+    fn share_log(dir: &tempfile::TempDir, name: &str) -> Arc<ShareLog> {
+        Arc::new(ShareLog::with_path(dir.path().join(name)))
+    }
+
+    /// The miner side of a farm connection, running in the background.
+    struct TestMiner {
+        templates: watch::Receiver<Template>,
+        nonce_ranges: watch::Receiver<NonceRange>,
+        submissions: mpsc::Sender<Submission>,
+        share_log: Arc<ShareLog>,
+    }
+
+    fn spawn_miner(address: String, farm_secret: Option<&str>, share_log: Arc<ShareLog>) -> TestMiner {
+        let (template_tx, templates) = watch::channel(template(0));
+        let (nonce_range_tx, nonce_ranges) = watch::channel(NonceRange::FULL);
+        let (submissions, submission_rx) = mpsc::channel(SUBMISSION_QUEUE);
+        tokio::spawn(run_client(
+            address,
+            farm_secret.map(|secret| FarmKey::derive(secret.as_bytes()).unwrap()),
+            "rig-1".to_string(),
+            4,
+            template_tx,
+            nonce_range_tx,
+            submission_rx,
+            share_log.clone(),
+            MinerStatus::new(false),
+        ));
+        TestMiner { templates, nonce_ranges, submissions, share_log }
+    }
+
+    #[tokio::test]
+    async fn test_frames_round_trip() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        let (_, mut sink) = client.into_split();
+        let (mut source, _) = server.into_split();
+
+        let hello = MinerMessage::Hello { worker: "rig-1".to_string(), threads: 8, version: "1.0".to_string() };
+        write_frame(&mut sink, &hello).await.unwrap();
+        match bincode::deserialize(&source.recv().await.unwrap()).unwrap() {
+            MinerMessage::Hello { worker, threads, .. } => assert_eq!((worker.as_str(), threads), ("rig-1", 8)),
+            message => panic!("unexpected {:?}", message),
+        }
+
+        // A length prefix past the limit is refused before anything is allocated.
+        sink.write_u32(MAX_FRAME_LEN as u32 + 1).await.unwrap();
+        assert!(source.recv().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shares_and_verdicts_travel_through_the_proxy() {
+        let dir = tempfile::tempdir().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (_upstream_templates, template_rx) = watch::channel(template(1));
+        let (submission_tx, mut upstream) = mpsc::channel(SUBMISSION_QUEUE);
+        let proxy_log = share_log(&dir, "proxy.jsonl");
+        tokio::spawn(serve_listener(listener, None, template_rx, submission_tx, proxy_log.clone()));
+
+        let mut miner = spawn_miner(address, None, share_log(&dir, "miner.jsonl"));
+        let mut outcomes = miner.share_log.subscribe();
+        timeout(WAIT, miner.templates.changed()).await.unwrap().unwrap();
+        let current = miner.templates.borrow().clone();
+        assert_eq!(current.commit.as_ref(), [1]);
+
+        let submission = Submission::new(Target::Pool, current.commit.clone(), Bytes::from_static(&[0xab]), Bytes::from_static(b"proof"));
+        miner.share_log.record_found(ShareContext::new(2, &current, &submission));
+        miner.submissions.send(submission).await.unwrap();
+
+        // The share reaches the upstream queue attributed to the worker.
+        let forwarded = timeout(WAIT, upstream.recv()).await.unwrap().unwrap();
+        assert_eq!(forwarded.digest.as_ref(), [0xab]);
+        let share = proxy_log.mark_submitted(&forwarded).unwrap();
+        assert_eq!(share.worker.as_deref(), Some("rig-1"));
+        assert_eq!(share.thread_id, 2);
+
+        // And the pool's verdict comes back to the miner that found it.
+        proxy_log.record_outcome(Some("stale template".to_string()));
+        let outcome = timeout(WAIT, outcomes.recv()).await.unwrap().unwrap();
+        assert_eq!(outcome.share.digest, "ab");
+        assert_eq!(outcome.rejection.as_deref(), Some("stale template"));
+    }
+
+    #[tokio::test]
+    async fn test_coordinator_assigns_nonce_ranges() {
+        let dir = tempfile::tempdir().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (_upstream_templates, template_rx) = watch::channel(template(1));
+        let (submission_tx, _upstream) = mpsc::channel(SUBMISSION_QUEUE);
+        let farm_key = FarmKey::derive(b"farm secret").unwrap();
+        tokio::spawn(serve_listener(listener, Some(farm_key), template_rx, submission_tx, share_log(&dir, "coordinator.jsonl")));
+
+        let mut miner = spawn_miner(address, Some("farm secret"), share_log(&dir, "worker.jsonl"));
+        timeout(WAIT, miner.nonce_ranges.wait_for(|range| *range != NonceRange::FULL)).await.unwrap().unwrap();
+        assert_eq!(*miner.nonce_ranges.borrow(), NonceRange::partition(0, NONCE_PARTITIONS));
+        timeout(WAIT, miner.templates.wait_for(|template| template.commit.as_ref() == [1])).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_client_reconnects_after_the_proxy_drops_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _miner = spawn_miner(listener.local_addr().unwrap().to_string(), None, share_log(&dir, "miner.jsonl"));
+
+        for _ in 0..2 {
+            let (stream, _) = timeout(WAIT, listener.accept()).await.unwrap().unwrap();
+            let (mut source, _) = stream.into_split();
+            let hello = bincode::deserialize(&source.recv().await.unwrap()).unwrap();
+            assert!(matches!(hello, MinerMessage::Hello { threads: 4, .. }));
+            // Dropping the connection here sends the miner back to connect again.
+        }
+    }
+}
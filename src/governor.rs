@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+// Decides how many mining threads should be running right now. Several
+// independent sources (the time-of-day schedule, the thermal governor, ...)
+// each impose an optional cap, and the miner follows the smallest of them
+// through a watch channel, booting or retiring serfs as the target moves.
+
+#[derive(Debug)]
+struct GovernorState {
+    base: u64,
+    caps: BTreeMap<&'static str, u64>,
+}
+
+impl GovernorState {
+    fn target(&self) -> u64 {
+        self.caps.values().copied().fold(self.base, u64::min)
+    }
+}
+
+#[derive(Debug)]
+pub struct ThreadGovernor {
+    max_threads: u64,
+    state: Mutex<GovernorState>,
+    target_tx: watch::Sender<u64>,
+}
+
+impl ThreadGovernor {
+    pub fn new(max_threads: u64) -> Arc<Self> {
+        let (target_tx, _) = watch::channel(max_threads);
+        Arc::new(Self {
+            max_threads,
+            state: Mutex::new(GovernorState {
+                base: max_threads,
+                caps: BTreeMap::new(),
+            }),
+            target_tx,
+        })
+    }
+
+    /// Thread count the machine was configured for; caps are relative to this.
+    pub fn max_threads(&self) -> u64 {
+        self.max_threads
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.target_tx.subscribe()
+    }
+
+    /// Threads the miner is currently asked to run.
+    pub fn target(&self) -> u64 {
+        *self.target_tx.borrow()
+    }
+
+    /// Changes the operator-chosen thread count, up to `max_threads`. Caps
+    /// still apply on top of it.
+    pub fn set_threads(&self, threads: u64) {
+        let mut state = self.state.lock().expect("governor poisoned");
+        state.base = threads.min(self.max_threads);
+        self.publish(&state, "operator");
+    }
+
+    /// Adds `delta` threads to the operator-chosen count, clamped to `0..=max_threads`.
+    pub fn adjust_threads(&self, delta: i64) {
+        let mut state = self.state.lock().expect("governor poisoned");
+        state.base = state.base.saturating_add_signed(delta).min(self.max_threads);
+        self.publish(&state, "operator");
+    }
+
+    /// Stops every mining thread until resumed, on top of any other limits.
+    pub fn set_paused(&self, paused: bool) {
+        self.set_cap("pause", paused.then_some(0));
+    }
+
+    pub fn paused(&self) -> bool {
+        self.state.lock().expect("governor poisoned").caps.contains_key("pause")
+    }
+
+    /// Sets or clears the cap imposed by `source`.
+    pub fn set_cap(&self, source: &'static str, cap: Option<u64>) {
+        let mut state = self.state.lock().expect("governor poisoned");
+        match cap {
+            Some(cap) => state.caps.insert(source, cap),
+            None => state.caps.remove(source),
+        };
+        self.publish(&state, source);
+    }
+
+    fn publish(&self, state: &GovernorState, source: &'static str) {
+        let target = state.target();
+        let changed = self.target_tx.send_if_modified(|current| {
+            if *current == target {
+                return false;
+            }
+            *current = target;
+            true
+        });
+        if changed {
+            info!("{} set mining threads to {} of {}", source, target, self.max_threads);
+        }
+    }
+}
+
+/// Lets operators resize the pool without a restart: `SIGUSR1` adds a
+/// mining thread and `SIGUSR2` retires one.
+#[cfg(unix)]
+pub fn spawn_signal_handler(governor: Arc<ThreadGovernor>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let (mut more, mut fewer) = match (signal(SignalKind::user_defined1()), signal(SignalKind::user_defined2())) {
+        (Ok(more), Ok(fewer)) => (more, fewer),
+        (Err(e), _) | (_, Err(e)) => {
+            warn!("failed to install thread count signal handlers: {}", e);
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        loop {
+            let delta = tokio::select! {
+                Some(()) = more.recv() => 1,
+                Some(()) = fewer.recv() => -1,
+                else => break,
+            };
+            governor.adjust_threads(delta);
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn spawn_signal_handler(_governor: Arc<ThreadGovernor>) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_governor_takes_smallest_limit() {
+        let governor = ThreadGovernor::new(8);
+        let target = governor.subscribe();
+
+        governor.set_cap("schedule", Some(6));
+        governor.set_cap("thermal", Some(4));
+        assert_eq!(*target.borrow(), 4);
+
+        governor.set_cap("thermal", None);
+        assert_eq!(*target.borrow(), 6);
+
+        governor.adjust_threads(-3);
+        assert_eq!(governor.target(), 5);
+        governor.set_threads(32);
+        assert_eq!(governor.target(), 6);
+        governor.set_cap("schedule", None);
+        assert_eq!(governor.target(), 8);
+    }
+}
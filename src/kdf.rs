@@ -0,0 +1,33 @@
+use anyhow::{anyhow, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use zeroize::Zeroizing;
+
+// Argon2id stretching for secrets chosen by people: the key store passphrase
+// and the farm secret. Both start from the same cost so it is raised in one
+// place; the key store records the cost it used alongside each file.
+
+/// Argon2id cost parameters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cost {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+/// Keep the tests fast; real secrets use the recommended 64 MiB.
+pub const DEFAULT_COST: Cost = Cost {
+    memory_kib: if cfg!(test) { 1024 } else { 64 * 1024 },
+    iterations: 3,
+    parallelism: 1,
+};
+
+/// Stretches `secret` into a 32-byte key.
+pub fn stretch(secret: &[u8], salt: &[u8], cost: Cost) -> Result<Zeroizing<[u8; 32]>> {
+    let params = Params::new(cost.memory_kib, cost.iterations, cost.parallelism, Some(32))
+        .map_err(|e| anyhow!("invalid Argon2 parameters: {}", e))?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(secret, salt, key.as_mut())
+        .map_err(|e| anyhow!("Argon2id failed: {}", e))?;
+    Ok(key)
+}
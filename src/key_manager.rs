@@ -1,59 +1,239 @@
 use anyhow::{anyhow, Result};
-use tracing::info;
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{error, info, warn};
 
 use crate::auth::SupabaseAuth;
-use crate::config::Config;
-use crate::key_storage::KeyStorage;
+use crate::config::{ApiArgs, Config, KeyArgs, KeyStoreArgs, KeysCommand};
+use crate::key_storage::{self, KeyEnvironment, KeyStorage, Passphrase, StoredKey, DEFAULT_PROFILE};
+use crate::net::HttpOptions;
+use crate::pool_session::Reconnect;
+use crate::secrets::Secret;
 
 pub struct KeyManager {
     storage: KeyStorage,
     auth: SupabaseAuth,
+    profile: String,
+    environment: KeyEnvironment,
+    account_token: Option<Secret>,
+    token_expiry_days: Option<u32>,
+    /// The newest stored key; pool sessions switch to it when they reconnect.
+    current: Mutex<Option<StoredKey>>,
+}
+
+/// Rotate keys this long before they expire, at the latest.
+const MIN_ROTATION_LEAD: chrono::Duration = chrono::Duration::hours(1);
+/// How long to wait before trying a failed rotation again.
+const ROTATION_RETRY: Duration = Duration::from_secs(600);
+
+/// When `stored` should be replaced: a fifth of its lifetime before it
+/// expires, and at least an hour before. Keys created without an expiry
+/// count as expiring `expiry_days` after creation when rotation is required.
+fn rotation_due(stored: &StoredKey, expiry_days: Option<u32>) -> Option<DateTime<Utc>> {
+    let expires_at = stored
+        .expires_at
+        .or_else(|| expiry_days.map(|days| stored.created_at + chrono::Duration::days(days as i64)))?;
+    let lead = ((expires_at - stored.created_at) / 5).max(MIN_ROTATION_LEAD);
+    Some(expires_at - lead)
 }
 
 impl KeyManager {
-    pub fn new() -> Result<Self> {
+    pub fn new(keys: &KeyArgs, api: &ApiArgs, can_prompt: bool) -> Result<Self> {
         Ok(Self {
-            storage: KeyStorage::new()?,
-            auth: SupabaseAuth::new(),
+            storage: KeyStorage::new()?.with_passphrase(Passphrase::from_config(keys), can_prompt),
+            auth: SupabaseAuth::new(&HttpOptions::from_config(api)?)?,
+            profile: keys.profile.clone(),
+            environment: KeyEnvironment::from_config(api),
+            account_token: keys.account_token.clone(),
+            token_expiry_days: keys.token_expiry_days,
+            current: Mutex::new(None),
         })
     }
 
     pub async fn get_mining_key(&self, config: &Config) -> Result<String> {
         // If a key is explicitly provided via CLI, use it directly
         if let Some(key) = &config.key {
-            info!("Using mining key provided via --key argument");
-            return Ok(key.clone());
+            info!("Using the mining key given with --key or --key-file");
+            return Ok(key.expose().to_string());
         }
 
-        // Check if we have a stored key
-        if let Some(stored_key) = self.storage.load_key()? {
-            info!("Using stored mining key");
-            return Ok(stored_key);
+        // Check if we have a stored key for this profile and environment
+        self.migrate()?;
+        if let Some(stored_key) = self.stored_key()? {
+            match stored_key.expires_at {
+                Some(expires_at) if expires_at <= Utc::now() => {
+                    warn!("Stored mining key expired on {}", expires_at.format("%Y-%m-%d"));
+                }
+                _ => {
+                    info!("Using stored mining key created {}", stored_key.created_at.format("%Y-%m-%d"));
+                    let key = stored_key.key.clone();
+                    *self.current.lock().expect("key manager poisoned") = Some(stored_key);
+                    return Ok(key);
+                }
+            }
         }
 
         // No stored key, need to create one using account token
-        let account_token = config.account_token.as_ref()
-            .ok_or_else(|| anyhow!("Account token is required for authentication"))?;
+        let account_token = self.account_token.as_ref()
+            .ok_or_else(|| anyhow!("Either --key or --account-token must be provided"))?;
 
-        info!("No stored mining key found, creating new one using account token...");
-        
+        info!("No usable stored mining key found, creating new one using account token...");
+        let new_key = self.create_key(account_token.expose()).await?;
+        info!("Successfully created and stored new mining key");
+        Ok(new_key)
+    }
+
+    /// Creates a key with the account token, stores it and makes it current.
+    async fn create_key(&self, account_token: &str) -> Result<String> {
         // Generate a nickname for the device (optional)
         let device_nickname = self.generate_device_nickname();
-        
+
         // Create new mining token via account token
-        let new_key = self.auth.get_or_create_mining_token(account_token, device_nickname, &config.api_url).await?;
-        
+        let new_key = self
+            .auth
+            .get_or_create_mining_token(account_token, device_nickname.clone(), self.token_expiry_days, &self.environment.api_url)
+            .await?;
+        let expires_at = self.token_expiry_days.map(|days| Utc::now() + chrono::Duration::days(days as i64));
+
         // Store the new key locally
-        self.storage.save_key(&new_key)?;
-        
-        info!("Successfully created and stored new mining key");
+        let stored = StoredKey::new(&self.profile, new_key.clone(), &self.environment, device_nickname, expires_at);
+        self.storage.save_key(stored.clone())?;
+        *self.current.lock().expect("key manager poisoned") = Some(stored);
         Ok(new_key)
     }
 
+    /// The newest key, if it came from the key store.
+    pub fn current_key(&self) -> Option<String> {
+        self.current.lock().expect("key manager poisoned").as_ref().map(|stored| stored.key.clone())
+    }
+
+    /// Revokes a key that has been replaced, so it can't be used again.
+    pub async fn revoke_key(&self, key: &str) -> Result<()> {
+        self.revoke(key, &self.environment.api_url).await?;
+        info!("Revoked the previous mining key {}", key_storage::redact(key));
+        Ok(())
+    }
+
+    /// Revokes `key`. A key the API doesn't know is as good as revoked.
+    async fn revoke(&self, key: &str, api_url: &str) -> Result<()> {
+        let account_token = self.account_token.as_ref().ok_or_else(|| anyhow!("No --account-token to revoke keys with"))?;
+        if !self.auth.revoke_mining_token(account_token.expose(), key, api_url).await? {
+            info!("Mining key {} was already revoked", key_storage::redact(key));
+        }
+        Ok(())
+    }
+
+    /// Replaces the current key ahead of its expiry, then has the pool
+    /// sessions reconnect so they switch to the new key and revoke the old one.
+    pub fn spawn_rotation(self: &Arc<Self>, reconnect_tx: broadcast::Sender<Reconnect>) {
+        if self.account_token.is_none() {
+            return;
+        }
+        let key_manager = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let Some(current) = key_manager.current.lock().expect("key manager poisoned").clone() else {
+                    return;
+                };
+                let Some(due) = rotation_due(&current, key_manager.token_expiry_days) else {
+                    return;
+                };
+                let wait = (due - Utc::now()).to_std().unwrap_or(Duration::ZERO);
+                if !wait.is_zero() {
+                    info!("Mining key will be rotated on {}", due.format("%Y-%m-%d %H:%M UTC"));
+                    tokio::time::sleep(wait).await;
+                }
+
+                let account_token = key_manager.account_token.as_ref().expect("checked above");
+                match key_manager.create_key(account_token.expose()).await {
+                    Ok(_) => {
+                        info!("Created a replacement mining key, reconnecting to switch to it");
+                        // The old key is no longer stored, so it is revoked as the sessions leave it.
+                        let _ = reconnect_tx.send(Reconnect::Requested);
+                    }
+                    Err(e) => {
+                        error!("Failed to rotate the mining key: {:#}", e);
+                        tokio::time::sleep(ROTATION_RETRY).await;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Replaces a key the pool no longer accepts: the dead key is dropped
+    /// from storage and a new one is created with the account token.
+    pub async fn replace_revoked_key(&self, revoked: &str) -> Result<String> {
+        let account_token = self.account_token.as_ref().ok_or_else(|| {
+            anyhow!("The mining key was revoked or is invalid and no --account-token is set to create a new one")
+        })?;
+
+        if self.storage.remove_key(&self.profile, &self.environment, revoked)? {
+            warn!("Deleted the revoked mining key from {}", self.get_key_storage_path());
+        }
+
+        let new_key = self.create_key(account_token.expose()).await?;
+        info!("Created and stored a replacement mining key");
+        Ok(new_key)
+    }
+
+    /// The stored key of this profile and environment, expired or not.
+    pub fn stored_key(&self) -> Result<Option<StoredKey>> {
+        self.storage.load_key(&self.profile, &self.environment)
+    }
+
+    /// Brings a key left by an older miner into the store.
+    fn migrate(&self) -> Result<()> {
+        self.storage.migrate(&self.environment)
+    }
+
+    pub fn has_legacy_key(&self) -> bool {
+        self.storage.has_legacy_key()
+    }
+
     pub fn clear_stored_key(&self) -> Result<()> {
         self.storage.delete_key()
     }
 
+    /// Deletes the keys of this profile, or every stored key with `all`.
+    fn clear_keys(&self, all: bool) -> Result<()> {
+        let profile = &self.profile;
+        if all {
+            self.clear_stored_key()?;
+            println!("Deleted every stored mining key in {}", self.get_key_storage_path());
+            return Ok(());
+        }
+        match self.delete_profile(profile)? {
+            0 => Err(anyhow!("No stored keys in profile {}", profile)),
+            removed => {
+                println!("Deleted {} stored key(s) of profile {}", removed, profile);
+                Ok(())
+            }
+        }
+    }
+
+    /// Revokes the stored key of this profile with the pool, then deletes it,
+    /// so a refused revocation leaves the key in place.
+    async fn revoke_stored_key(&self) -> Result<()> {
+        let profile = &self.profile;
+        let stored = self.stored_key()?
+            .ok_or_else(|| anyhow!("No stored key in profile {} for {}", profile, self.environment.api_url))?;
+        self.revoke(&stored.key, &stored.api_url).await?;
+        self.storage.remove_key(profile, &self.environment, &stored.key)?;
+        println!("Revoked and deleted mining key {} of profile {}", key_storage::redact(&stored.key), profile);
+        Ok(())
+    }
+
+    /// Every stored key, across all profiles and environments.
+    pub fn stored_keys(&self) -> Result<Vec<StoredKey>> {
+        self.storage.keys()
+    }
+
+    pub fn delete_profile(&self, profile: &str) -> Result<usize> {
+        self.storage.delete_profile(profile)
+    }
+
 
     pub fn get_key_storage_path(&self) -> String {
         self.storage.get_key_file_path().display().to_string()
@@ -63,20 +243,10 @@ impl KeyManager {
         use uuid::Uuid;
         
         // Try to get hostname as nickname
-        let base_name = match std::env::var("HOSTNAME")
-            .or_else(|_| std::env::var("COMPUTERNAME"))
-            .or_else(|_| {
-                // Try reading from /etc/hostname on Unix systems
-                std::fs::read_to_string("/etc/hostname")
-                    .map(|s| s.trim().to_string())
-            }) {
-            Ok(hostname) if !hostname.is_empty() => {
-                format!("miner-{}", hostname)
-            }
-            _ => {
-                // Fallback to a generic name
-                "miner".to_string()
-            }
+        let base_name = match crate::device::hostname() {
+            Some(hostname) => format!("miner-{}", hostname),
+            // Fallback to a generic name
+            None => "miner".to_string(),
         };
         
         // Append UUID to ensure uniqueness
@@ -86,12 +256,156 @@ impl KeyManager {
     }
 }
 
-pub async fn resolve_mining_key(config: &Config) -> Result<String> {
-    // Validate authentication configuration
-    if let Err(e) = config.validate_auth() {
-        return Err(anyhow!("Authentication configuration error: {}", e));
+/// Handles the `keys` subcommands.
+pub async fn run_keys_command(command: KeysCommand) -> Result<()> {
+    let store = command.store();
+    let key_manager = KeyManager::new(&store.keys, &store.api, true)?;
+    let profile = &key_manager.profile;
+    // Listing and showing keys only read the store.
+    if matches!(command, KeysCommand::Create(_) | KeysCommand::Clear { .. }) {
+        key_manager.migrate()?;
+    }
+
+    match command {
+        KeysCommand::List(_) => print_key_table(&key_manager),
+        KeysCommand::Show(_) => {
+            let keys = key_manager.stored_keys()?;
+            let keys: Vec<&StoredKey> = keys.iter().filter(|stored| &stored.profile == profile).collect();
+            if keys.is_empty() {
+                return Err(anyhow!("No stored keys in profile {}", profile));
+            }
+            for stored in keys {
+                println!("profile:         {}", stored.profile);
+                println!("key:             {}", key_storage::redact(&stored.key));
+                println!("api url:         {}", stored.api_url);
+                println!("server address:  {}", stored.server_address);
+                println!("created:         {}", stored.created_at.to_rfc3339());
+                println!("device nickname: {}", stored.device_nickname.as_deref().unwrap_or("-"));
+                println!("expires:         {}", stored.expires_at.map_or("never".to_string(), |at| at.to_rfc3339()));
+                println!();
+            }
+            Ok(())
+        }
+        KeysCommand::Clear { all, .. } => key_manager.clear_keys(all),
+        KeysCommand::Create(_) => {
+            let account_token = key_manager.account_token.as_ref()
+                .ok_or_else(|| anyhow!("`keys create` needs --account-token"))?;
+            let replaced = key_manager.stored_key()?;
+            let key = key_manager.create_key(account_token.expose()).await?;
+            println!("Created and stored mining key {} in profile {}", key_storage::redact(&key), profile);
+            if let Some(replaced) = replaced {
+                println!(
+                    "It replaces {}, which stays valid until revoked",
+                    key_storage::redact(&replaced.key)
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Handles `unstable revoke-key`.
+pub async fn run_revoke_key_command(store: &KeyStoreArgs) -> Result<()> {
+    let key_manager = KeyManager::new(&store.keys, &store.api, true)?;
+    key_manager.migrate()?;
+    key_manager.revoke_stored_key().await
+}
+
+fn print_key_table(key_manager: &KeyManager) -> Result<()> {
+    if key_manager.has_legacy_key() {
+        println!("A key from an older miner is present; it moves into profile {} on the next run", DEFAULT_PROFILE);
+    }
+    let keys = key_manager.stored_keys()?;
+    if keys.is_empty() {
+        println!("No stored mining keys in {}", key_manager.get_key_storage_path());
+        return Ok(());
+    }
+    println!("{:<16}{:<32}{:<28}{:<12}KEY", "PROFILE", "API URL", "SERVER", "CREATED");
+    for stored in &keys {
+        println!(
+            "{:<16}{:<32}{:<28}{:<12}{}",
+            stored.profile,
+            stored.api_url,
+            stored.server_address,
+            stored.created_at.format("%Y-%m-%d"),
+            key_storage::redact(&stored.key)
+        );
+    }
+    Ok(())
+}
+
+/// The mining key to use, and the key manager that can replace it if the
+/// pool stops accepting it.
+pub async fn resolve_mining_key(config: &Config) -> Result<(String, Arc<KeyManager>)> {
+    // The terminal UI owns the terminal, so it can't prompt.
+    let key_manager = KeyManager::new(&config.keys, &config.api, !config.tui)?;
+    let key = key_manager.get_mining_key(config).await?;
+    Ok((key, Arc::new(key_manager)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn environment(api_url: &str) -> KeyEnvironment {
+        KeyEnvironment {
+            api_url: api_url.to_string(),
+            server_address: "nockpool.com:27016".to_string(),
+        }
     }
 
-    let key_manager = KeyManager::new()?;
-    key_manager.get_mining_key(config).await
-}
\ No newline at end of file
+    #[test]
+    fn test_rotation_due() {
+        let environment = environment("https://nockpool.com");
+        let mut stored = StoredKey::new("default", "nock_key".to_string(), &environment, None, None);
+        assert_eq!(rotation_due(&stored, None), None);
+
+        // A 30 day key is rotated six days early.
+        stored.expires_at = Some(stored.created_at + chrono::Duration::days(30));
+        assert_eq!(rotation_due(&stored, None), Some(stored.created_at + chrono::Duration::days(24)));
+
+        // Short-lived keys still get an hour's notice.
+        stored.expires_at = Some(stored.created_at + chrono::Duration::hours(2));
+        assert_eq!(rotation_due(&stored, None), Some(stored.created_at + chrono::Duration::hours(1)));
+
+        // Keys without an expiry follow the configured policy.
+        stored.expires_at = None;
+        assert_eq!(rotation_due(&stored, Some(10)), Some(stored.created_at + chrono::Duration::days(8)));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_deletes_only_revoked_keys() {
+        let api_url = crate::auth::tests::mock_api().await;
+        let temp_dir = tempfile::tempdir().unwrap();
+        let environment = environment(&api_url);
+        let key_manager = KeyManager {
+            storage: KeyStorage::in_dir(temp_dir.path().to_path_buf()),
+            auth: SupabaseAuth::new(&HttpOptions::default()).unwrap(),
+            profile: "default".to_string(),
+            environment: environment.clone(),
+            account_token: Some(Secret::new("nockacct_test".to_string())),
+            token_expiry_days: None,
+            current: Mutex::new(None),
+        };
+        let store = |profile: &str, key: &str| {
+            let stored = StoredKey::new(profile, key.to_string(), &environment, None, None);
+            key_manager.storage.save_key(stored).unwrap();
+        };
+
+        // A refused revocation keeps the key.
+        store("default", "nock_locked");
+        let error = key_manager.revoke_stored_key().await.unwrap_err();
+        assert!(error.to_string().contains("permission to revoke"), "{}", error);
+        assert_eq!(key_manager.stored_keys().unwrap().len(), 1);
+
+        // The live key replaces the refused one.
+        store("default", "nock_live");
+        key_manager.revoke_stored_key().await.unwrap();
+        assert!(key_manager.stored_keys().unwrap().is_empty());
+
+        // A key the API no longer knows counts as revoked.
+        store("default", "nock_gone");
+        key_manager.revoke_stored_key().await.unwrap();
+        assert!(key_manager.stored_keys().unwrap().is_empty());
+    }
+}
@@ -1,83 +1,534 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
 use directories::ProjectDirs;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305};
+use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use tracing::{info, warn};
+use zeroize::Zeroizing;
+
+use crate::config::{ApiArgs, KeyArgs};
+use crate::kdf;
 
 const APP_NAME: &str = "nockpool";
-const KEY_FILENAME: &str = "mining_key.txt";
+const KEY_STORE_FILENAME: &str = "keys.json";
+const ENCRYPTED_KEY_STORE_FILENAME: &str = "keys.enc";
+// A single bare key, as stored before keys carried metadata.
+const LEGACY_KEY_FILENAME: &str = "mining_key.txt";
+const LEGACY_ENCRYPTED_KEY_FILENAME: &str = "mining_key.enc";
+const KEY_STORE_VERSION: u32 = 1;
+pub const DEFAULT_PROFILE: &str = "default";
+
+// Stored keys live in a versioned JSON document, one entry per profile and
+// API environment so a key minted against staging is never offered to
+// production. With a passphrase the whole document is sealed with
+// ChaCha20-Poly1305 under a key derived from the passphrase with Argon2id.
+// The KDF parameters travel with the file so they can be raised later
+// without breaking existing keys.
+const ENCRYPTED_KEY_VERSION: u32 = 1;
+const ENCRYPTED_KEY_AAD: &[u8] = b"nockpool mining key v1";
+
+/// Where the passphrase for an encrypted key comes from.
+pub enum Passphrase {
+    Value(Zeroizing<String>),
+    File(PathBuf),
+    Prompt,
+}
+
+impl Passphrase {
+    /// The configured source, if the stored key should be encrypted.
+    pub fn from_config(config: &KeyArgs) -> Option<Self> {
+        if let Some(passphrase) = &config.key_passphrase {
+            Some(Passphrase::Value(Zeroizing::new(passphrase.expose().to_string())))
+        } else if let Some(path) = &config.key_passphrase_file {
+            Some(Passphrase::File(path.clone()))
+        } else if config.encrypt_key {
+            Some(Passphrase::Prompt)
+        } else {
+            None
+        }
+    }
+}
+
+/// The API environment a mining key was created for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyEnvironment {
+    pub api_url: String,
+    pub server_address: String,
+}
+
+impl KeyEnvironment {
+    pub fn from_config(config: &ApiArgs) -> Self {
+        Self {
+            api_url: config.api_url.trim_end_matches('/').to_string(),
+            server_address: config.server_address.clone(),
+        }
+    }
+}
+
+fn default_profile() -> String {
+    DEFAULT_PROFILE.to_string()
+}
+
+/// Shows enough of a key to tell keys apart without revealing it.
+pub fn redact(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() <= 12 {
+        return "*".repeat(chars.len());
+    }
+    let head: String = chars[..6].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}...{}", head, tail)
+}
+
+/// A mining key and what we know about where it came from.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StoredKey {
+    #[serde(default = "default_profile")]
+    pub profile: String,
+    pub key: String,
+    pub created_at: DateTime<Utc>,
+    pub api_url: String,
+    pub server_address: String,
+    #[serde(default)]
+    pub device_nickname: Option<String>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl StoredKey {
+    pub fn new(
+        profile: &str,
+        key: String,
+        environment: &KeyEnvironment,
+        device_nickname: Option<String>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Self {
+        Self {
+            profile: profile.to_string(),
+            key,
+            created_at: Utc::now(),
+            api_url: environment.api_url.clone(),
+            server_address: environment.server_address.clone(),
+            device_nickname,
+            expires_at,
+        }
+    }
+
+    pub fn environment(&self) -> KeyEnvironment {
+        KeyEnvironment {
+            api_url: self.api_url.trim_end_matches('/').to_string(),
+            server_address: self.server_address.clone(),
+        }
+    }
+
+    pub fn belongs_to(&self, environment: &KeyEnvironment) -> bool {
+        self.api_url.trim_end_matches('/') == environment.api_url.trim_end_matches('/')
+            && self.server_address == environment.server_address
+    }
+
+    pub fn matches(&self, profile: &str, environment: &KeyEnvironment) -> bool {
+        self.profile == profile && self.belongs_to(environment)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct KeyStoreDocument {
+    version: u32,
+    keys: Vec<StoredKey>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedKey {
+    version: u32,
+    kdf: KdfParams,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct KdfParams {
+    algorithm: String,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+    salt: String,
+}
 
 pub struct KeyStorage {
     config_dir: PathBuf,
-    key_file_path: PathBuf,
+    store_path: PathBuf,
+    encrypted_store_path: PathBuf,
+    passphrase: Option<Passphrase>,
+    can_prompt: bool,
+    resolved: OnceLock<Zeroizing<String>>,
+}
+
+/// Per-user config directory shared by everything the miner persists.
+pub fn config_dir() -> Result<PathBuf> {
+    let project_dirs = ProjectDirs::from("", "", APP_NAME)
+        .ok_or_else(|| anyhow!("Could not determine config directory"))?;
+    Ok(project_dirs.config_dir().to_path_buf())
+}
+
+/// Replaces `path` atomically with a file only the current user can read.
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let dir = path.parent().ok_or_else(|| anyhow!("{} has no parent directory", path.display()))?;
+    fs::create_dir_all(dir)?;
+    // Temp files are created with mode 0600 on Unix, and the rename keeps it.
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    file.write_all(contents)?;
+    file.as_file().sync_all()?;
+    file.persist(path).map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e.error))?;
+    Ok(())
+}
+
+fn derive_key(passphrase: &str, kdf: &KdfParams) -> Result<LessSafeKey> {
+    if kdf.algorithm != "argon2id" {
+        bail!("Unsupported key derivation algorithm '{}'", kdf.algorithm);
+    }
+    let salt = hex::decode(&kdf.salt)?;
+    let cost = kdf::Cost { memory_kib: kdf.memory_kib, iterations: kdf.iterations, parallelism: kdf.parallelism };
+    let key = kdf::stretch(passphrase.as_bytes(), &salt, cost).context("Failed to derive key from passphrase")?;
+    let key = UnboundKey::new(&CHACHA20_POLY1305, key.as_ref()).map_err(|_| anyhow!("Failed to build key encryption key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+fn seal(plaintext: &[u8], passphrase: &str) -> Result<EncryptedKey> {
+    let kdf = KdfParams {
+        algorithm: "argon2id".to_string(),
+        memory_kib: kdf::DEFAULT_COST.memory_kib,
+        iterations: kdf::DEFAULT_COST.iterations,
+        parallelism: kdf::DEFAULT_COST.parallelism,
+        salt: hex::encode(rand::random::<[u8; 16]>()),
+    };
+    let nonce: [u8; 12] = rand::random();
+    let mut sealed = plaintext.to_vec();
+    derive_key(passphrase, &kdf)?
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(ENCRYPTED_KEY_AAD), &mut sealed)
+        .map_err(|_| anyhow!("Failed to encrypt the key store"))?;
+    Ok(EncryptedKey {
+        version: ENCRYPTED_KEY_VERSION,
+        kdf,
+        nonce: hex::encode(nonce),
+        ciphertext: hex::encode(sealed),
+    })
+}
+
+fn unseal(encrypted: &EncryptedKey, passphrase: &str) -> Result<Zeroizing<Vec<u8>>> {
+    if encrypted.version != ENCRYPTED_KEY_VERSION {
+        bail!("Unsupported encrypted key version {}", encrypted.version);
+    }
+    let nonce: [u8; 12] = hex::decode(&encrypted.nonce)?
+        .try_into()
+        .map_err(|_| anyhow!("Encrypted key has a malformed nonce"))?;
+    let mut sealed = Zeroizing::new(hex::decode(&encrypted.ciphertext)?);
+    let plaintext = derive_key(passphrase, &encrypted.kdf)?
+        .open_in_place(Nonce::assume_unique_for_key(nonce), Aad::from(ENCRYPTED_KEY_AAD), &mut sealed)
+        .map_err(|_| anyhow!("Failed to decrypt the key store: wrong passphrase or corrupted file"))?;
+    Ok(Zeroizing::new(plaintext.to_vec()))
+}
+
+/// Reads a line from the terminal without echoing it.
+fn read_hidden(prompt: &str) -> Result<Zeroizing<String>> {
+    use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+
+    eprint!("{}", prompt);
+    std::io::stderr().flush()?;
+    crossterm::terminal::enable_raw_mode()?;
+    let mut line = Zeroizing::new(String::new());
+    let result = loop {
+        let key = match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+            Ok(_) => continue,
+            Err(e) => break Err(e.into()),
+        };
+        match key.code {
+            KeyCode::Enter => break Ok(()),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                break Err(anyhow!("Passphrase entry cancelled"))
+            }
+            KeyCode::Char(c) => line.push(c),
+            KeyCode::Backspace => {
+                line.pop();
+            }
+            _ => {}
+        }
+    };
+    crossterm::terminal::disable_raw_mode()?;
+    eprintln!();
+    result.map(|_| line)
+}
+
+fn prompt_passphrase(confirm: bool) -> Result<Zeroizing<String>> {
+    let passphrase = read_hidden("Mining key passphrase: ")?;
+    if confirm && read_hidden("Confirm passphrase: ")? != passphrase {
+        bail!("Passphrases do not match");
+    }
+    Ok(passphrase)
 }
 
 impl KeyStorage {
     pub fn new() -> Result<Self> {
-        let project_dirs = ProjectDirs::from("", "", APP_NAME)
-            .ok_or_else(|| anyhow!("Could not determine config directory"))?;
-        
-        let config_dir = project_dirs.config_dir().to_path_buf();
-        let key_file_path = config_dir.join(KEY_FILENAME);
+        Ok(Self::in_dir(config_dir()?))
+    }
 
-        Ok(Self {
+    pub(crate) fn in_dir(config_dir: PathBuf) -> Self {
+        Self {
+            store_path: config_dir.join(KEY_STORE_FILENAME),
+            encrypted_store_path: config_dir.join(ENCRYPTED_KEY_STORE_FILENAME),
             config_dir,
-            key_file_path,
-        })
+            passphrase: None,
+            can_prompt: false,
+            resolved: OnceLock::new(),
+        }
     }
 
-    pub fn load_key(&self) -> Result<Option<String>> {
-        if !self.key_file_path.exists() {
-            info!("No stored mining key found at {}", self.key_file_path.display());
-            return Ok(None);
-        }
+    /// Encrypts the key store with `passphrase`. An already encrypted store
+    /// can only be read with a passphrase; when none is configured the
+    /// operator is prompted for it if `can_prompt` allows.
+    pub fn with_passphrase(mut self, passphrase: Option<Passphrase>, can_prompt: bool) -> Self {
+        self.passphrase = passphrase;
+        self.can_prompt = can_prompt;
+        self
+    }
 
-        match fs::read_to_string(&self.key_file_path) {
-            Ok(key) => {
-                let key = key.trim().to_string();
-                if key.is_empty() {
-                    warn!("Stored mining key file is empty");
-                    return Ok(None);
-                }
-                info!("Loaded mining key from {}", self.key_file_path.display());
-                Ok(Some(key))
+    fn passphrase(&self, confirm: bool) -> Result<&Zeroizing<String>> {
+        if let Some(passphrase) = self.resolved.get() {
+            return Ok(passphrase);
+        }
+        let passphrase = match &self.passphrase {
+            Some(Passphrase::Value(passphrase)) => passphrase.clone(),
+            Some(Passphrase::File(path)) => {
+                let contents = Zeroizing::new(
+                    fs::read_to_string(path).map_err(|e| anyhow!("Failed to read passphrase file {}: {}", path.display(), e))?,
+                );
+                Zeroizing::new(contents.trim_end_matches(['\r', '\n']).to_string())
             }
-            Err(e) => {
-                warn!("Failed to read mining key file: {}", e);
-                Ok(None)
+            Some(Passphrase::Prompt) | None => {
+                if !self.can_prompt || !std::io::stdin().is_terminal() {
+                    bail!("The mining key passphrase is needed; set NOCKPOOL_KEY_PASSPHRASE or --key-passphrase-file");
+                }
+                prompt_passphrase(confirm)?
             }
+        };
+        if passphrase.is_empty() {
+            bail!("The mining key passphrase is empty");
+        }
+        Ok(self.resolved.get_or_init(|| passphrase))
+    }
+
+    fn read_encrypted(&self, path: &Path) -> Result<Zeroizing<Vec<u8>>> {
+        let encrypted: EncryptedKey = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| anyhow!("Failed to parse {}: {}", path.display(), e))?;
+        unseal(&encrypted, self.passphrase(false)?)
+    }
+
+    /// The key store document and whether it was encrypted.
+    fn read_document(&self) -> Result<Option<(KeyStoreDocument, bool)>> {
+        let (contents, encrypted) = if self.encrypted_store_path.exists() {
+            (self.read_encrypted(&self.encrypted_store_path)?, true)
+        } else if self.store_path.exists() {
+            (Zeroizing::new(fs::read(&self.store_path)?), false)
+        } else {
+            return Ok(None);
+        };
+        let document: KeyStoreDocument =
+            serde_json::from_slice(&contents).map_err(|e| anyhow!("Failed to parse the key store: {}", e))?;
+        if document.version > KEY_STORE_VERSION {
+            bail!("The key store was written by a newer miner (version {})", document.version);
         }
+        Ok(Some((document, encrypted)))
     }
 
-    pub fn save_key(&self, key: &str) -> Result<()> {
+    fn write_document(&self, document: &KeyStoreDocument, encrypt: bool) -> Result<()> {
         // Create config directory if it doesn't exist
         if !self.config_dir.exists() {
             fs::create_dir_all(&self.config_dir)?;
             info!("Created config directory: {}", self.config_dir.display());
         }
 
-        // Save key to file
-        fs::write(&self.key_file_path, key)?;
-        info!("Saved mining key to {}", self.key_file_path.display());
-        
+        let contents = Zeroizing::new(serde_json::to_vec_pretty(document)?);
+        if encrypt {
+            let encrypted = seal(&contents, self.passphrase(true)?)?;
+            write_private(&self.encrypted_store_path, serde_json::to_string_pretty(&encrypted)?.as_bytes())?;
+            if self.store_path.exists() {
+                fs::remove_file(&self.store_path)?;
+            }
+        } else {
+            write_private(&self.store_path, &contents)?;
+        }
+        Ok(())
+    }
+
+    /// A bare key left by an older miner, and when it was written.
+    fn read_legacy(&self) -> Result<Option<(String, DateTime<Utc>, bool)>> {
+        let legacy_path = self.config_dir.join(LEGACY_KEY_FILENAME);
+        let legacy_encrypted_path = self.config_dir.join(LEGACY_ENCRYPTED_KEY_FILENAME);
+        let (path, contents, encrypted) = if legacy_encrypted_path.exists() {
+            let contents = self.read_encrypted(&legacy_encrypted_path)?;
+            (legacy_encrypted_path, contents, true)
+        } else if legacy_path.exists() {
+            let contents = Zeroizing::new(fs::read(&legacy_path)?);
+            (legacy_path, contents, false)
+        } else {
+            return Ok(None);
+        };
+
+        let key = String::from_utf8_lossy(&contents).trim().to_string();
+        if key.is_empty() {
+            warn!("Stored mining key file {} is empty", path.display());
+            return Ok(None);
+        }
+        let written_at = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_else(|_| Utc::now());
+        Ok(Some((key, written_at, encrypted)))
+    }
+
+    /// Whether a key left by an older miner is waiting to be migrated.
+    pub fn has_legacy_key(&self) -> bool {
+        [LEGACY_KEY_FILENAME, LEGACY_ENCRYPTED_KEY_FILENAME]
+            .iter()
+            .any(|legacy| self.config_dir.join(legacy).exists())
+    }
+
+    /// Moves a key left by an older miner, which recorded no environment,
+    /// into the default profile as belonging to `environment`, and encrypts
+    /// a plaintext store once a passphrase is configured. Only running and
+    /// the commands that write keys call this; reading never rewrites the store.
+    pub fn migrate(&self, environment: &KeyEnvironment) -> Result<()> {
+        let existing = self.read_document()?;
+        let mut rewrite = matches!(existing, Some((_, false))) && self.passphrase.is_some();
+        let (mut document, mut encrypt) = existing.unwrap_or_default();
+        encrypt |= self.passphrase.is_some();
+
+        if let Some((key, created_at, encrypted)) = self.read_legacy()? {
+            info!("Moving the stored mining key into {}, assuming it belongs to {}", KEY_STORE_FILENAME, environment.api_url);
+            if !document.keys.iter().any(|stored| stored.key == key) {
+                document.keys.push(StoredKey {
+                    created_at,
+                    ..StoredKey::new(DEFAULT_PROFILE, key, environment, None, None)
+                });
+            }
+            encrypt |= encrypted;
+            rewrite = true;
+        }
+
+        if rewrite {
+            document.version = KEY_STORE_VERSION;
+            match self.write_document(&document, encrypt) {
+                Ok(()) => {
+                    for legacy in [LEGACY_KEY_FILENAME, LEGACY_ENCRYPTED_KEY_FILENAME] {
+                        let path = self.config_dir.join(legacy);
+                        if path.exists() {
+                            fs::remove_file(&path)?;
+                        }
+                    }
+                    info!("Saved the key store to {}", self.get_key_file_path().display());
+                }
+                Err(e) => warn!("Failed to migrate the stored mining key: {:#}", e),
+            }
+        }
         Ok(())
     }
 
+    /// Every stored key, across all profiles and environments.
+    pub fn keys(&self) -> Result<Vec<StoredKey>> {
+        Ok(self.read_document()?.map(|(document, _)| document.keys).unwrap_or_default())
+    }
+
+    /// The key stored under `profile` for `environment`, if any.
+    pub fn load_key(&self, profile: &str, environment: &KeyEnvironment) -> Result<Option<StoredKey>> {
+        let keys: Vec<StoredKey> = self.keys()?.into_iter().filter(|stored| stored.profile == profile).collect();
+        let others = keys.iter().filter(|stored| !stored.belongs_to(environment)).count();
+        match keys.into_iter().find(|stored| stored.belongs_to(environment)) {
+            Some(stored) => {
+                info!("Loaded mining key for profile {} and {} from {}", profile, environment.api_url, self.get_key_file_path().display());
+                Ok(Some(stored))
+            }
+            None => {
+                if others > 0 {
+                    info!("Ignoring {} stored mining key(s) created for other environments", others);
+                }
+                info!("No stored mining key found for profile {} and {}", profile, environment.api_url);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Stores `key`, replacing any earlier key for the same profile and environment.
+    pub fn save_key(&self, key: StoredKey) -> Result<()> {
+        let (mut document, encrypted) = self.read_document()?.unwrap_or_default();
+        let (profile, environment) = (key.profile.clone(), key.environment());
+        document.version = KEY_STORE_VERSION;
+        document.keys.retain(|stored| !stored.matches(&profile, &environment));
+        document.keys.push(key);
+        self.write_document(&document, encrypted || self.passphrase.is_some())?;
+        info!("Saved mining key for profile {} and {} to {}", profile, environment.api_url, self.get_key_file_path().display());
+        Ok(())
+    }
+
+    /// Removes `key` from `profile` for `environment`, if it is stored there.
+    pub fn remove_key(&self, profile: &str, environment: &KeyEnvironment, key: &str) -> Result<bool> {
+        let Some((mut document, encrypted)) = self.read_document()? else {
+            return Ok(false);
+        };
+        let before = document.keys.len();
+        document.keys.retain(|stored| !(stored.matches(profile, environment) && stored.key == key));
+        if document.keys.len() == before {
+            return Ok(false);
+        }
+        self.write_document(&document, encrypted)?;
+        Ok(true)
+    }
+
+    /// Removes every key stored under `profile` and returns how many there were.
+    pub fn delete_profile(&self, profile: &str) -> Result<usize> {
+        let Some((mut document, encrypted)) = self.read_document()? else {
+            return Ok(0);
+        };
+        let before = document.keys.len();
+        document.keys.retain(|stored| stored.profile != profile);
+        let removed = before - document.keys.len();
+        if removed > 0 {
+            self.write_document(&document, encrypted)?;
+            info!("Deleted {} mining key(s) of profile {}", removed, profile);
+        }
+        Ok(removed)
+    }
+
     pub fn delete_key(&self) -> Result<()> {
-        if self.key_file_path.exists() {
-            fs::remove_file(&self.key_file_path)?;
-            info!("Deleted mining key file: {}", self.key_file_path.display());
+        for filename in [
+            KEY_STORE_FILENAME,
+            ENCRYPTED_KEY_STORE_FILENAME,
+            LEGACY_KEY_FILENAME,
+            LEGACY_ENCRYPTED_KEY_FILENAME,
+        ] {
+            let path = self.config_dir.join(filename);
+            if path.exists() {
+                fs::remove_file(&path)?;
+                info!("Deleted mining key file: {}", path.display());
+            }
         }
         Ok(())
     }
 
     #[cfg(test)]
     pub fn key_exists(&self) -> bool {
-        self.key_file_path.exists()
+        self.store_path.exists() || self.encrypted_store_path.exists()
     }
 
     pub fn get_key_file_path(&self) -> &PathBuf {
-        &self.key_file_path
+        if self.passphrase.is_some() || self.encrypted_store_path.exists() {
+            &self.encrypted_store_path
+        } else {
+            &self.store_path
+        }
     }
 }
 
@@ -86,29 +537,112 @@ mod tests {
     use super::*;
     use tempfile::tempdir;
 
+    fn environment(api_url: &str) -> KeyEnvironment {
+        KeyEnvironment {
+            api_url: api_url.to_string(),
+            server_address: "nockpool.com:27016".to_string(),
+        }
+    }
+
     #[test]
     fn test_key_storage_operations() {
         let temp_dir = tempdir().unwrap();
-        let config_dir = temp_dir.path().to_path_buf();
-        let key_file_path = config_dir.join(KEY_FILENAME);
-        
-        let storage = KeyStorage {
-            config_dir,
-            key_file_path,
-        };
+        let storage = KeyStorage::in_dir(temp_dir.path().to_path_buf());
+        let production = environment("https://nockpool.com");
 
         // Test no key initially
         assert!(!storage.key_exists());
-        assert!(storage.load_key().unwrap().is_none());
+        assert!(storage.load_key(DEFAULT_PROFILE, &production).unwrap().is_none());
 
         // Test save and load
         let test_key = "nock_1234567890abcdef";
-        storage.save_key(test_key).unwrap();
+        storage
+            .save_key(StoredKey::new(DEFAULT_PROFILE, test_key.to_string(), &production, Some("miner-test".to_string()), None))
+            .unwrap();
         assert!(storage.key_exists());
-        assert_eq!(storage.load_key().unwrap().unwrap(), test_key);
+        let stored = storage.load_key(DEFAULT_PROFILE, &production).unwrap().unwrap();
+        assert_eq!(stored.key, test_key);
+        assert_eq!(stored.device_nickname.as_deref(), Some("miner-test"));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(storage.get_key_file_path()).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
 
         // Test delete
         storage.delete_key().unwrap();
         assert!(!storage.key_exists());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_keys_are_scoped_to_their_environment() {
+        let temp_dir = tempdir().unwrap();
+        let storage = KeyStorage::in_dir(temp_dir.path().to_path_buf());
+        let production = environment("https://nockpool.com");
+        let staging = environment("https://staging.nockpool.com");
+
+        storage.save_key(StoredKey::new(DEFAULT_PROFILE, "nock_staging".to_string(), &staging, None, None)).unwrap();
+        assert!(storage.load_key(DEFAULT_PROFILE, &production).unwrap().is_none());
+
+        storage.save_key(StoredKey::new(DEFAULT_PROFILE, "nock_production".to_string(), &production, None, None)).unwrap();
+        storage.save_key(StoredKey::new(DEFAULT_PROFILE, "nock_production_2".to_string(), &production, None, None)).unwrap();
+        assert_eq!(storage.load_key(DEFAULT_PROFILE, &production).unwrap().unwrap().key, "nock_production_2");
+        let staging_slash = environment("https://staging.nockpool.com/");
+        assert_eq!(storage.load_key(DEFAULT_PROFILE, &staging_slash).unwrap().unwrap().key, "nock_staging");
+        assert_eq!(storage.keys().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_profiles_hold_separate_keys() {
+        let temp_dir = tempdir().unwrap();
+        let storage = KeyStorage::in_dir(temp_dir.path().to_path_buf());
+        let production = environment("https://nockpool.com");
+
+        storage.save_key(StoredKey::new(DEFAULT_PROFILE, "nock_default".to_string(), &production, None, None)).unwrap();
+        storage.save_key(StoredKey::new("team", "nock_team".to_string(), &production, None, None)).unwrap();
+        assert_eq!(storage.load_key("team", &production).unwrap().unwrap().key, "nock_team");
+        assert_eq!(storage.load_key(DEFAULT_PROFILE, &production).unwrap().unwrap().key, "nock_default");
+
+        assert_eq!(storage.delete_profile("team").unwrap(), 1);
+        assert!(storage.load_key("team", &production).unwrap().is_none());
+        assert_eq!(storage.load_key(DEFAULT_PROFILE, &production).unwrap().unwrap().key, "nock_default");
+
+        assert_eq!(redact("nock_1234567890abcdef"), "nock_1...cdef");
+        assert_eq!(redact("short"), "*****");
+    }
+
+    #[test]
+    fn test_legacy_key_migrates_to_encrypted_store() {
+        let temp_dir = tempdir().unwrap();
+        let production = environment("https://nockpool.com");
+        let passphrase = || Some(Passphrase::Value(Zeroizing::new("correct horse".to_string())));
+        fs::write(temp_dir.path().join(LEGACY_KEY_FILENAME), "nock_plain\n").unwrap();
+
+        // Reading leaves the old key where it is.
+        let storage = KeyStorage::in_dir(temp_dir.path().to_path_buf()).with_passphrase(passphrase(), false);
+        assert!(storage.has_legacy_key());
+        assert!(storage.load_key(DEFAULT_PROFILE, &production).unwrap().is_none());
+        assert!(temp_dir.path().join(LEGACY_KEY_FILENAME).exists());
+
+        storage.migrate(&production).unwrap();
+        assert!(!storage.has_legacy_key());
+        let stored = storage.load_key(DEFAULT_PROFILE, &production).unwrap().unwrap();
+        assert_eq!(stored.key, "nock_plain");
+        assert_eq!(stored.api_url, "https://nockpool.com");
+        assert!(!temp_dir.path().join(LEGACY_KEY_FILENAME).exists());
+        let sealed = fs::read_to_string(temp_dir.path().join(ENCRYPTED_KEY_STORE_FILENAME)).unwrap();
+        assert!(!sealed.contains("nock_plain"));
+
+        let reopened = KeyStorage::in_dir(temp_dir.path().to_path_buf()).with_passphrase(passphrase(), false);
+        assert_eq!(reopened.load_key(DEFAULT_PROFILE, &production).unwrap().unwrap().key, "nock_plain");
+
+        let wrong = KeyStorage::in_dir(temp_dir.path().to_path_buf())
+            .with_passphrase(Some(Passphrase::Value(Zeroizing::new("wrong".to_string()))), false);
+        assert!(wrong.load_key(DEFAULT_PROFILE, &production).is_err());
+
+        // Without a passphrase source the store can't be read, and isn't replaced.
+        assert!(KeyStorage::in_dir(temp_dir.path().to_path_buf()).load_key(DEFAULT_PROFILE, &production).is_err());
+    }
+}
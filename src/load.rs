@@ -0,0 +1,121 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use sysinfo::System;
+use tracing::info;
+
+use crate::governor::ThreadGovernor;
+
+// Load-aware mining for shared workstations. Samples the CPU used by every
+// process other than the miner and gives those cores back straight away;
+// threads only return once the machine has stayed quiet for the idle period,
+// so a build or a video call is never left fighting the prover.
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Cores left free for the desktop even when nothing else is running,
+/// matching the default thread count.
+const RESERVED_CORES: u64 = 2;
+
+#[derive(Debug)]
+struct LoadState {
+    idle_after: Duration,
+    cap: Option<u64>,
+    /// Since when the budget has been above the current cap.
+    quiet_since: Option<Instant>,
+}
+
+impl LoadState {
+    /// Moves the cap towards `budget`, shrinking immediately and growing
+    /// only after the budget has stayed higher for `idle_after`.
+    fn update(&mut self, budget: u64, max_threads: u64, now: Instant) -> Option<u64> {
+        let target = (budget < max_threads).then_some(budget);
+        let current = self.cap.unwrap_or(max_threads);
+
+        if budget < current {
+            self.quiet_since = None;
+            self.cap = target;
+        } else if budget > current {
+            let quiet_since = *self.quiet_since.get_or_insert(now);
+            if now.duration_since(quiet_since) >= self.idle_after {
+                self.quiet_since = None;
+                self.cap = target;
+            }
+        } else {
+            self.quiet_since = None;
+        }
+        self.cap
+    }
+}
+
+/// Threads the miner may use while other processes keep `foreign_cores` busy.
+fn budget(logical_cores: u64, foreign_cores: f32) -> u64 {
+    logical_cores
+        .saturating_sub(RESERVED_CORES)
+        .saturating_sub(foreign_cores.round() as u64)
+}
+
+pub fn spawn(idle_after: Duration, governor: Arc<ThreadGovernor>) {
+    tokio::spawn(async move {
+        let own_pid = sysinfo::get_current_pid().ok();
+        let mut sys = System::new();
+        sys.refresh_cpu();
+        let logical_cores = sys.cpus().len() as u64;
+
+        let mut state = LoadState { idle_after, cap: None, quiet_since: None };
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            sys.refresh_processes();
+            let foreign_cores: f32 = sys
+                .processes()
+                .iter()
+                .filter(|(pid, _)| Some(**pid) != own_pid)
+                .map(|(_, process)| process.cpu_usage() / 100.0)
+                .sum();
+
+            let previous = state.cap;
+            let cap = state.update(budget(logical_cores, foreign_cores), governor.max_threads(), Instant::now());
+            if cap != previous {
+                let load = System::load_average();
+                match cap {
+                    Some(threads) if threads < previous.unwrap_or(u64::MAX) => info!(
+                        "other processes using {:.1} cores (load {:.2}), backing off to {} mining threads",
+                        foreign_cores, load.one, threads
+                    ),
+                    Some(threads) => info!("machine is quieter (load {:.2}), raising to {} mining threads", load.one, threads),
+                    None => info!("machine is idle (load {:.2}), mining at full power", load.one),
+                }
+                governor.set_cap("load", cap);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_backs_off_fast_and_returns_slowly() {
+        let start = Instant::now();
+        let idle_after = Duration::from_secs(60);
+        let mut state = LoadState { idle_after, cap: None, quiet_since: None };
+
+        assert_eq!(budget(16, 0.2), 14);
+        assert_eq!(budget(16, 5.6), 8);
+        assert_eq!(budget(4, 9.0), 0);
+
+        assert_eq!(state.update(14, 14, start), None);
+        assert_eq!(state.update(8, 14, start), Some(8));
+        // The load goes away, but the threads wait out the idle period.
+        assert_eq!(state.update(14, 14, start + Duration::from_secs(5)), Some(8));
+        assert_eq!(state.update(14, 14, start + Duration::from_secs(30)), Some(8));
+        assert_eq!(state.update(14, 14, start + Duration::from_secs(65)), None);
+        // A spike during the idle period restarts it.
+        assert_eq!(state.update(4, 14, start + Duration::from_secs(70)), Some(4));
+        assert_eq!(state.update(12, 14, start + Duration::from_secs(75)), Some(4));
+        assert_eq!(state.update(2, 14, start + Duration::from_secs(80)), Some(2));
+        assert_eq!(state.update(12, 14, start + Duration::from_secs(85)), Some(2));
+        assert_eq!(state.update(12, 14, start + Duration::from_secs(145)), Some(12));
+    }
+}
@@ -0,0 +1,234 @@
+use anyhow::{anyhow, Result};
+use chrono::{Local, NaiveDate};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc;
+
+// Log file with rotation and retention for rigs without journald. The live
+// file keeps its configured name; when it rolls over (at a size limit or at
+// local midnight) it is renamed with a timestamp suffix. A background worker
+// then gzips it and deletes the oldest rotated files beyond the retention
+// count, one rotation at a time so pruning never races a compression.
+
+/// When the live log file is rolled over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    Daily,
+    Size(u64),
+}
+
+impl FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.trim().to_ascii_lowercase();
+        if lower == "daily" {
+            return Ok(Rotation::Daily);
+        }
+        let (digits, unit) = lower.split_at(lower.find(|c: char| !c.is_ascii_digit()).unwrap_or(lower.len()));
+        let multiplier = match unit.trim() {
+            "" | "b" => 1,
+            "k" | "kb" => 1 << 10,
+            "m" | "mb" => 1 << 20,
+            "g" | "gb" => 1 << 30,
+            _ => return Err(format!("Invalid rotation '{}': expected 'daily' or a size like '100MB'", s)),
+        };
+        let size: u64 = digits
+            .parse()
+            .map_err(|_| format!("Invalid rotation '{}': expected 'daily' or a size like '100MB'", s))?;
+        if size == 0 {
+            return Err(format!("Invalid rotation '{}': size must be positive", s));
+        }
+        size.checked_mul(multiplier)
+            .map(Rotation::Size)
+            .ok_or_else(|| format!("Invalid rotation '{}': size is too large", s))
+    }
+}
+
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    rotation: Rotation,
+    file: File,
+    size: u64,
+    day: NaiveDate,
+    /// Hands rotated files to the worker that compresses and prunes them.
+    rotated_tx: mpsc::Sender<PathBuf>,
+}
+
+impl RotatingFile {
+    pub fn open(path: PathBuf, rotation: Rotation, keep: usize, compress: bool) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| anyhow!("Failed to open log file {}: {}", path.display(), e))?;
+        let size = file.metadata()?.len();
+        let rotated_tx = spawn_housekeeping(path.clone(), keep, compress);
+        Ok(Self {
+            path,
+            rotation,
+            file,
+            size,
+            day: Local::now().date_naive(),
+            rotated_tx,
+        })
+    }
+
+    fn should_rotate(&self, incoming: usize, today: NaiveDate) -> bool {
+        match self.rotation {
+            Rotation::Daily => today != self.day,
+            Rotation::Size(limit) => self.size > 0 && self.size + incoming as u64 > limit,
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let suffix = Local::now().format("%Y%m%dT%H%M%S%.3f");
+        let rotated = PathBuf::from(format!("{}.{}", self.path.display(), suffix));
+        fs::rename(&self.path, &rotated)?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        // Compressing a large file takes a while; don't hold up logging for it.
+        // The worker only goes away with this file, so this can't fail.
+        let _ = self.rotated_tx.send(rotated);
+        Ok(())
+    }
+}
+
+/// Starts the worker that compresses each rotated file of `path` and then
+/// prunes old ones. It exits once the returned sender is dropped.
+fn spawn_housekeeping(path: PathBuf, keep: usize, compress: bool) -> mpsc::Sender<PathBuf> {
+    let (rotated_tx, rotated_rx) = mpsc::channel::<PathBuf>();
+    std::thread::spawn(move || {
+        for rotated in rotated_rx {
+            if compress {
+                if let Err(e) = gzip_file(&rotated) {
+                    eprintln!("failed to compress {}: {}", rotated.display(), e);
+                }
+            }
+            if let Err(e) = prune(&path, keep) {
+                eprintln!("failed to prune old logs of {}: {}", path.display(), e);
+            }
+        }
+    });
+    rotated_tx
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let today = Local::now().date_naive();
+        if self.should_rotate(buf.len(), today) {
+            self.rotate()?;
+        }
+        self.day = today;
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Rotated files of `path`, oldest first. Their timestamp suffixes sort by age.
+fn rotated_files(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let dir = match path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        Some(dir) => dir.to_path_buf(),
+        None => PathBuf::from("."),
+    };
+    let prefix = format!("{}.", path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default());
+    let mut rotated: Vec<PathBuf> = fs::read_dir(&dir)?
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with(&prefix) && !name.ends_with(".tmp")
+        })
+        .map(|entry| entry.path())
+        .collect();
+    rotated.sort();
+    Ok(rotated)
+}
+
+fn prune(path: &Path, keep: usize) -> io::Result<()> {
+    let rotated = rotated_files(path)?;
+    for old in rotated.iter().take(rotated.len().saturating_sub(keep)) {
+        fs::remove_file(old)?;
+    }
+    Ok(())
+}
+
+/// Replaces `path` with `path.gz`, streaming it through the encoder.
+fn gzip_file(path: &Path) -> io::Result<()> {
+    let gz_path = PathBuf::from(format!("{}.gz", path.display()));
+    let tmp_path = PathBuf::from(format!("{}.gz.tmp", path.display()));
+    let mut encoder = GzEncoder::new(File::create(&tmp_path)?, Compression::default());
+    io::copy(&mut File::open(path)?, &mut encoder)?;
+    encoder.finish()?;
+    fs::rename(&tmp_path, &gz_path)?;
+    fs::remove_file(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation_parsing() {
+        assert_eq!("daily".parse::<Rotation>().unwrap(), Rotation::Daily);
+        assert_eq!("100MB".parse::<Rotation>().unwrap(), Rotation::Size(100 << 20));
+        assert_eq!("512k".parse::<Rotation>().unwrap(), Rotation::Size(512 << 10));
+        assert!("0MB".parse::<Rotation>().is_err());
+        assert!("99999999999g".parse::<Rotation>().is_err());
+        assert!("weekly".parse::<Rotation>().is_err());
+    }
+
+    #[test]
+    fn test_gzip_file() {
+        use std::io::Read;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("miner.log.20260101T000000.000");
+        let data = "I (12:00:00) miner: solution found\n".repeat(100);
+        fs::write(&path, &data).unwrap();
+        gzip_file(&path).unwrap();
+        assert!(!path.exists());
+
+        let mut decoded = String::new();
+        let gz = File::open(dir.path().join("miner.log.20260101T000000.000.gz")).unwrap();
+        flate2::read::GzDecoder::new(gz).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn test_size_rotation_and_retention() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("miner.log");
+        let mut file = RotatingFile::open(path.clone(), Rotation::Size(64), 2, false).unwrap();
+
+        for i in 0..5 {
+            file.write_all(format!("{:<47}\n", i).as_bytes()).unwrap();
+            // Keep the rotated names distinct.
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap().len(), 48);
+
+        // Retention runs in the background after each rotation.
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while rotated_files(&path).unwrap().len() > 2 && std::time::Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let rotated = rotated_files(&path).unwrap();
+        assert_eq!(rotated.len(), 2);
+        assert!(fs::read_to_string(&rotated[1]).unwrap().starts_with('3'));
+    }
+}
@@ -1,71 +1,122 @@
 mod tracer;
+mod log_file;
 mod new_job;
 mod config;
+mod config_file;
 mod device;
 mod miner;
 mod submission;
 mod auth;
+mod account;
 mod key_storage;
 mod key_manager;
+mod share_log;
+mod net;
+mod retry;
+mod tls;
+mod secrets;
+mod farm;
+mod secure_channel;
+mod kdf;
+mod pool_session;
+mod stats;
+mod governor;
+mod schedule;
+mod thermal;
+mod load;
+mod status;
+mod control;
+mod dashboard;
+mod tui;
+mod diagnose;
 
 use crate::new_job::NockPoolNewJobConsumer;
-use crate::submission::{NockPoolSubmissionProvider, NockPoolSubmissionResponseHandler};
-use crate::config::Config;
-use crate::key_manager::{resolve_mining_key, KeyManager};
+use crate::submission::{NockPoolSubmissionProvider, NockPoolSubmissionResponseHandler, SUBMISSION_QUEUE};
+use crate::config::{Command, Config, ConfigCommand, Mode};
+use crate::key_manager::{resolve_mining_key, run_keys_command};
+use crate::share_log::ShareLog;
+use crate::miner::{MiningSession, NonceRange};
+use crate::pool_session::{PoolSession, Reconnect};
+use crate::secure_channel::FarmKey;
+use crate::stats::ShareStats;
+use crate::governor::ThreadGovernor;
+use crate::thermal::ThermalLimit;
+use crate::status::MinerStatus;
+use crate::control::ControlApi;
+use crate::dashboard::Dashboard;
+use crate::tui::Tui;
 
-use clap::Parser;
-use tokio::sync::{watch, mpsc};
-use tracing::{error, info};
+use anyhow::{anyhow, Context};
+use tokio::sync::{broadcast, watch, mpsc};
+use tokio::task::JoinSet;
+use tracing::error;
 use std::sync::Arc;
-use quiver::types::{Template, Submission, Target};
+use std::time::Duration;
+use quiver::types::{Template, Submission};
 use bytes::Bytes;
 
 #[tokio::main]
 async fn main() {
-    tracer::init();
+    let cli = config_file::parse();
+    let log = cli.log.clone();
+    let config_path = cli.config_file.clone();
+    let (command, deprecated) = cli.into_command();
+    let tui = matches!(&command, Command::Run(config) if config.tui);
 
-    let config = Config::parse();
-
-    if config.benchmark {
-        tracing::info!("Running benchmark...");
-        if let Err(e) = miner::benchmark().await {
-            tracing::error!("Error running benchmark: {}", e);
-        }
-        tracing::info!("Benchmark completed successfully");
+    if let Err(e) = tracer::init(&log, tui) {
+        eprintln!("Failed to set up logging: {:#}", e);
         return;
     }
-
-    if config.clear_key {
-        tracing::info!("Clearing stored mining key...");
-        match KeyManager::new() {
-            Ok(key_manager) => {
-                match key_manager.clear_stored_key() {
-                    Ok(()) => {
-                        tracing::info!("Stored mining key cleared successfully");
-                        tracing::info!("Key was stored at: {}", key_manager.get_key_storage_path());
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to clear stored key: {}", e);
-                    }
-                }
-            }
-            Err(e) => {
-                tracing::error!("Failed to initialize key manager: {}", e);
-            }
-        }
-        return;
+    if let Some((flag, replacement)) = deprecated {
+        tracing::warn!("{} is deprecated; use `nockpool-miner {}` instead", flag, replacement);
     }
 
-    // --- Template Provider ---
-    let (template_tx, template_rx) = watch::channel(Template::new(Bytes::new(), Bytes::new(), Bytes::new(), Bytes::new(), Bytes::new()));
-    let new_job_consumer = Arc::new(NockPoolNewJobConsumer::new(template_tx));
+    let mut command = command;
+    if let Err(e) = command.resolve_secrets() {
+        error!("{:#}", e);
+        std::process::exit(1);
+    }
 
-    // --- Submission Provider ---
-    let initial_submission = Submission::new(Target::Pool, Bytes::new(), Bytes::new(), Bytes::new());
-    let (submission_tx, submission_rx) = watch::channel(initial_submission);
-    let submission_provider = Arc::new(NockPoolSubmissionProvider::new(submission_rx));
+    let result = match command {
+        Command::Run(config) => run(*config).await,
+        Command::Bench => {
+            tracing::info!("Running benchmark...");
+            miner::benchmark().await.map(|()| tracing::info!("Benchmark completed successfully"))
+        }
+        Command::Keys { command } => run_keys_command(command).await,
+        Command::Unstable { command } => account::run_unstable_command(command).await,
+        Command::Config { command: ConfigCommand::Show(_) } => config_file::show(),
+        Command::Config { command: ConfigCommand::Init { force } } => config_file::init(config_path, force)
+            .map(|path| println!("Wrote {}", path.display())),
+        Command::Status(args) => control::print_status(&args).await,
+        Command::Diagnose(args) => diagnose::run(&args).await,
+    };
+    if let Err(e) = &result {
+        error!("{:#}", e);
+        // The terminal UI took the log stream with it.
+        if tui {
+            eprintln!("Error: {:#}", e);
+        }
+    }
+    tracer::shutdown().await;
+    if result.is_err() {
+        std::process::exit(1);
+    }
+}
 
-    let submission_response_handler = Arc::new(NockPoolSubmissionResponseHandler::new());
+async fn run(config: Config) -> anyhow::Result<()> {
+    // --- Thread Governor (how many threads mine right now) ---
+    let governor = ThreadGovernor::new(miner::thread_count(&config));
+    governor::spawn_signal_handler(governor.clone());
+    if let Some(schedule) = config.schedule.clone() {
+        schedule.spawn(governor.clone());
+    }
+    if let Some(limit) = config.thermal_limit {
+        ThermalLimit { limit, hysteresis: config.thermal_hysteresis }.spawn(governor.clone());
+    }
+    if config.load_aware {
+        load::spawn(Duration::from_secs(config.idle_after), governor.clone());
+    }
 
     // --- Gather System Info ---
     let device_info = device::get_device_info();
@@ -76,19 +127,93 @@ async fn main() {
         device_info.ram_capacity_gb
     );
 
+    // --- Live Status, Control API and Dashboard ---
+    let status = MinerStatus::new(config.network_only);
+    let (reconnect_tx, _) = broadcast::channel::<Reconnect>(16);
+    if config.control_api {
+        control::spawn(
+            config.control_address.clone(),
+            config.control_token.as_ref().map(|token| token.expose().to_string()),
+            ControlApi {
+                governor: governor.clone(),
+                status: status.clone(),
+                reconnect_tx: reconnect_tx.clone(),
+            },
+        );
+    }
+    if config.dashboard {
+        dashboard::spawn(
+            config.dashboard_address.clone(),
+            Dashboard {
+                governor: governor.clone(),
+                status: status.clone(),
+                device_info: device_info.clone(),
+                worker: worker_name(&config),
+            },
+        );
+    }
+    // Restores the terminal however this returns.
+    let mut tui = config.tui.then(|| {
+        Tui {
+            governor: governor.clone(),
+            status: status.clone(),
+            worker: worker_name(&config),
+        }
+        .spawn()
+    });
+
+    // --- Nonce Range (narrowed by a coordinator in worker mode) ---
+    let (nonce_range_tx, nonce_range_rx) = watch::channel(NonceRange::FULL);
+
+    // --- Farm Key (the farm secret stretched once, not on every connection) ---
+    let farm_key = config
+        .farm_secret
+        .as_ref()
+        .filter(|_| matches!(config.mode, Mode::Worker | Mode::Coordinator))
+        .map(|secret| FarmKey::derive(secret.expose().as_bytes()))
+        .transpose()
+        .context("Invalid farm secret")?;
+    // The parser can't require it, since a systemd credential may supply it.
+    if farm_key.is_none() && matches!(config.mode, Mode::Worker | Mode::Coordinator) {
+        return Err(anyhow!("--farm-secret is required in coordinator and worker modes"));
+    }
+
+    // --- Mine through a LAN proxy or coordinator instead of holding our own pool session ---
+    let (farm_upstream, upstream_key) = match config.mode {
+        Mode::Worker => (config.coordinator.clone(), farm_key.clone()),
+        _ => (config.farm_proxy.clone(), None),
+    };
+    if let Some(farm_upstream) = farm_upstream {
+        let (session, template_tx, submission_rx) = new_session(None, 1, &status)?;
+        tokio::spawn(farm::run_client(
+            farm_upstream,
+            upstream_key,
+            worker_name(&config),
+            governor.max_threads(),
+            template_tx,
+            nonce_range_tx,
+            submission_rx,
+            session.share_log.clone(),
+            status.clone(),
+        ));
+
+        return miner::start(vec![session], nonce_range_rx, governor.subscribe(), status)
+            .await
+            .context("Error running miner");
+    }
+
     // --- Set up panic hook for quiver client ---
-    let (panic_tx, mut panic_rx) = mpsc::unbounded_channel::<()>();
+    let panic_notifier = reconnect_tx.clone();
     
     std::panic::set_hook(Box::new(move |panic_info| {
         let payload = panic_info.payload().downcast_ref::<&str>()
             .unwrap_or(&"Unknown panic");
         
-        // Check if this is a quiver-related panic
-        if payload.contains("failed to open submission stream") || 
-           payload.contains("TimedOut") ||
-           panic_info.location().map_or(false, |l| l.file().contains("quiver")) {
+        // Quiver clients run on threads named after their session; only that session restarts.
+        let thread = std::thread::current();
+        if let Some(session) = thread.name().and_then(|name| name.strip_prefix(pool_session::CLIENT_THREAD_PREFIX)) {
             error!("Quiver client panic detected: {}", payload);
-            let _ = panic_tx.send(());
+            let _ = panic_notifier.send(Reconnect::Panic(session.to_string()));
         }
         
         // Print the panic info (preserving normal panic behavior)
@@ -99,90 +224,157 @@ async fn main() {
         eprintln!("{}", payload);
     }));
 
-    // --- Resolve mining key ---
-    let key = match resolve_mining_key(&config).await {
-        Ok(key) => key,
-        Err(e) => {
-            tracing::error!("Failed to resolve mining key: {}", e);
-            return;
+    if net::resolve_proxy(config.api.proxy.as_deref()).is_some() {
+        // The quiver client opens its own socket and has no proxy hook, so
+        // only the API client can honour the proxy setting. Someone relying
+        // on the proxy to hide or route all traffic must not find out later.
+        if !config.api.pool_direct {
+            return Err(anyhow!(
+                "A proxy is configured, but the pool connection cannot use one and would connect directly to {}. \
+                 Pass --pool-direct to allow that",
+                config.api.server_address
+            ));
         }
+        tracing::info!("Routing NockPool API requests through the configured proxy");
+        tracing::warn!("Connecting to the pool directly at {}, bypassing the proxy", config.api.server_address);
+    }
+
+    // --- Resolve mining keys ---
+    let mut key_manager = None;
+    let keys: Vec<(Option<String>, u32, String)> = if config.weighted_keys.is_empty() {
+        let (key, manager) = resolve_mining_key(&config).await.context("Failed to resolve mining key")?;
+        manager.spawn_rotation(reconnect_tx.clone());
+        key_manager = Some(manager);
+        vec![(None, 1, key)]
+    } else {
+        config.weighted_keys.iter()
+            .map(|weighted| (Some(weighted.label.clone()), weighted.weight, weighted.key.expose().to_string()))
+            .collect()
     };
-    let server_address = config.server_address.clone();
-    let client_address = config.client_address.clone();
-    let insecure = config.insecure.clone();
-    
-    tokio::spawn(async move {
-        let mut backoff_ms = 100_u64;
-        let max_backoff_ms = 30_000_u64;
-    
-        loop {
-            // Start the quiver client
-            let mut client_handle = tokio::spawn({
-                let server_address = server_address.clone();
-                let client_address = client_address.clone();
-                let key = key.clone();
-                let device_info = device_info.clone();
-                let new_job_consumer = new_job_consumer.clone();
-                let submission_provider = submission_provider.clone();
-                let submission_response_handler = submission_response_handler.clone();
-                
-                async move {
-                    quiver::client::run(
-                        insecure,
-                        server_address,
-                        client_address,
-                        key,
-                        device_info,
-                        new_job_consumer,
-                        submission_provider,
-                        submission_response_handler
-                    ).await
-                }
-            });
-
-            // Wait for either the client to finish or a panic to occur
-            let client_result = tokio::select! {
-                result = &mut client_handle => {
-                    Some(result)
-                }
-                _ = panic_rx.recv() => {
-                    error!("Panic detected in quiver client - triggering reconnection");
-                    client_handle.abort();
-                    None
-                }
-            };
-
-            match client_result {
-                Some(Ok(Ok(()))) => {
-                    info!("Client connection completed successfully, reconnecting immediately");
-                    backoff_ms = 100;
-                }
-                Some(Ok(Err(e))) => {
-                    error!("Client connection failed: {}", e);
-                    
-                    info!("Sleeping for {}ms before reconnecting", backoff_ms);
-                    tokio::time::sleep(tokio::time::Duration::from_millis(backoff_ms)).await;
-                    backoff_ms = (backoff_ms * 2).min(max_backoff_ms);
-                }
-                Some(Err(e)) => {
-                    error!("Client task failed: {}", e);
-                    
-                    info!("Sleeping for {}ms before reconnecting after task failure", backoff_ms);
-                    tokio::time::sleep(tokio::time::Duration::from_millis(backoff_ms)).await;
-                    backoff_ms = (backoff_ms * 2).min(max_backoff_ms);
-                }
-                None => {
-                    // Panic was detected
-                    info!("Sleeping for {}ms before reconnecting after panic", backoff_ms);
-                    tokio::time::sleep(tokio::time::Duration::from_millis(backoff_ms)).await;
-                    backoff_ms = (backoff_ms * 2).min(max_backoff_ms);
-                }
+    // --- Pool Sessions, one per key ---
+    let mut sessions = Vec::with_capacity(keys.len());
+    let mut pool_sessions = JoinSet::new();
+    for (index, (label, weight, key)) in keys.into_iter().enumerate() {
+        let client_address = pool_session::client_address_for(&config.client_address, index)
+            .context("Configuration error")?;
+        let (session, template_tx, submission_rx) = new_session(label.clone(), weight, &status)?;
+
+        // --- Template Provider ---
+        let new_job_consumer = Arc::new(NockPoolNewJobConsumer::new(template_tx));
+
+        // --- Submission Provider ---
+        let submission_provider = Arc::new(NockPoolSubmissionProvider::new(submission_rx, session.share_log.clone()));
+
+        let submission_response_handler = Arc::new(NockPoolSubmissionResponseHandler::new(session.share_log.clone()));
+
+        pool_sessions.spawn(pool_session::run(
+            PoolSession {
+                label,
+                insecure: config.insecure,
+                server_address: config.api.server_address.clone(),
+                client_address,
+                key,
+                device_info: device_info.clone(),
+                new_job_consumer,
+                submission_provider,
+                submission_response_handler,
+                share_log: session.share_log.clone(),
+                status: status.clone(),
+                key_manager: key_manager.clone(),
+            },
+            reconnect_tx.subscribe(),
+        ));
+        sessions.push(session);
+    }
+
+    let labelled: Vec<(String, Arc<ShareStats>)> = sessions.iter()
+        .filter_map(|session| session.label.clone().map(|label| (label, session.stats.clone())))
+        .collect();
+    if !labelled.is_empty() {
+        stats::spawn_reporter(labelled);
+    }
+
+    let work = async {
+        match config.mode {
+            // --- Serve LAN miners ---
+            Mode::Proxy | Mode::Coordinator => {
+                let session = sessions.into_iter().next().expect("proxy mode runs a single pool session");
+                farm::serve(config.listen_address.clone(), farm_key, session.template_rx, session.submission_tx, session.share_log)
+                    .await
+                    .context("Error running LAN proxy")
+            }
+            // --- Run the miner ---
+            Mode::Standalone | Mode::Worker => {
+                miner::start(sessions, nonce_range_rx, governor.subscribe(), status)
+                    .await
+                    .context("Error running miner")
             }
         }
-    });
+    };
+
+    let tui_quit = async {
+        match tui.as_mut() {
+            Some(tui) => tui.quit().await,
+            None => std::future::pending().await,
+        }
+    };
 
-    // --- Run the miner ---
-    if let Err(e) = miner::start(config, template_rx, submission_tx).await {
-        error!("Error running miner: {}", e);
+    tokio::select! {
+        result = work => result,
+        result = tui_quit => result,
+        result = pool_sessions_ended(&mut pool_sessions) => result,
+    }
+}
+
+/// Waits for every pool session to end. A session only ends once its key is
+/// refused for good; the miner carries on for the other keys, and stops with
+/// the last session's error.
+async fn pool_sessions_ended(pool_sessions: &mut JoinSet<anyhow::Result<()>>) -> anyhow::Result<()> {
+    let mut result = Ok(());
+    while let Some(ended) = pool_sessions.join_next().await {
+        result = ended.map_err(|e| anyhow!("Pool session failed: {}", e)).and_then(|result| result);
+        if let Err(e) = &result {
+            if !pool_sessions.is_empty() {
+                error!("{:#}", e);
+            }
+        }
     }
-} 
\ No newline at end of file
+    result
+}
+
+/// Name this rig reports to a LAN proxy or coordinator and shows on its dashboard.
+fn worker_name(config: &Config) -> String {
+    config.worker_name.clone()
+        .or_else(device::hostname)
+        .unwrap_or_else(|| "miner".to_string())
+}
+
+/// Channels, share log and counters for one pool session, plus the ends
+/// handed to whatever feeds it templates and drains its submissions.
+fn new_session(
+    label: Option<String>,
+    weight: u32,
+    status: &Arc<MinerStatus>,
+) -> anyhow::Result<(MiningSession, watch::Sender<Template>, mpsc::Receiver<Submission>)> {
+    // --- Rejected Share Log ---
+    let share_log = Arc::new(ShareLog::new().context("Failed to initialize rejected share log")?);
+    tracing::info!("Rejected shares will be logged to {}", share_log.path().display());
+
+    let stats = Arc::new(ShareStats::default());
+    stats.track(&share_log);
+    status.add_session(label.clone(), stats.clone(), &share_log);
+
+    // --- Template and Submission Channels ---
+    let (template_tx, template_rx) = watch::channel(Template::new(Bytes::new(), Bytes::new(), Bytes::new(), Bytes::new(), Bytes::new()));
+    let (submission_tx, submission_rx) = mpsc::channel::<Submission>(SUBMISSION_QUEUE);
+
+    let session = MiningSession {
+        label,
+        weight,
+        template_rx,
+        submission_tx,
+        share_log,
+        stats,
+    };
+    Ok((session, template_tx, submission_rx))
+}
@@ -1,14 +1,21 @@
 use crate::config::Config;
+use crate::share_log::{self, ShareContext, ShareLog};
+use crate::stats::ShareStats;
+use crate::status::{AttemptResult, MinerStatus};
 
 use quiver::types::{Template, Submission, Target};
 use kernels::miner::KERNEL;
 
 use sysinfo::System;
-use tokio::sync::{Mutex, watch};
+use tokio::sync::{mpsc, watch};
+use std::collections::BTreeMap;
+use std::sync::Arc;
 use anyhow::Result;
-use tracing::info;
+use std::time::Instant;
+use tracing::{field, info, info_span, warn, Instrument, Span};
 use rand::Rng;
 use bytes::Bytes;
+use serde::{Deserialize, Serialize};
 
 use nockapp::save::SaveableCheckpoint;
 use nockapp::utils::NOCK_STACK_SIZE_TINY;
@@ -25,32 +32,204 @@ use zkvm_jetpack::form::PRIME;
 
 use nockvm_macros::tas;
 
-pub async fn start(
-    config: Config,
-    mut template_rx: watch::Receiver<Template>,
-    submission_tx: watch::Sender<Submission>,
-) -> Result<()> {
-    let num_threads = {
-        let sys = System::new_all();
-        let logical_cores = sys.cpus().len() as u32;
-        let calculated_threads = logical_cores.saturating_sub(2).max(1);
-        if let Some(max_threads) = config.max_threads {
-            max_threads.min(calculated_threads) as u64
+/// Number of mining threads to run: all logical cores but two, capped by `--max-threads`.
+pub fn thread_count(config: &Config) -> u64 {
+    let sys = System::new_all();
+    let logical_cores = sys.cpus().len() as u32;
+    let calculated_threads = logical_cores.saturating_sub(2).max(1);
+    if let Some(max_threads) = config.max_threads {
+        max_threads.min(calculated_threads) as u64
+    } else {
+        calculated_threads as u64
+    }
+}
+
+/// Slice of the field one element of every fresh nonce is drawn from. Remote
+/// workers are each handed a disjoint range by their coordinator so no two
+/// machines ever search the same nonces.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NonceRange {
+    pub start: u64,
+    pub len: u64,
+}
+
+impl NonceRange {
+    pub const FULL: NonceRange = NonceRange { start: 0, len: PRIME };
+
+    /// The `index`-th of `count` equal slices of the field.
+    pub fn partition(index: u64, count: u64) -> NonceRange {
+        let len = PRIME / count;
+        NonceRange { start: (index % count) * len, len }
+    }
+
+    fn sample(&self, rng: &mut impl Rng) -> u64 {
+        self.start + rng.gen::<u64>() % self.len
+    }
+}
+
+/// One upstream pool session the miner works for. Sessions share the
+/// mining threads in proportion to their weights.
+pub struct MiningSession {
+    /// Name used in logs and per-key stats when mining for several keys.
+    pub label: Option<String>,
+    pub weight: u32,
+    pub template_rx: watch::Receiver<Template>,
+    pub submission_tx: mpsc::Sender<Submission>,
+    pub share_log: Arc<ShareLog>,
+    pub stats: Arc<ShareStats>,
+}
+
+/// Per-session state owned by the mining loop.
+struct SessionState {
+    label: Option<String>,
+    submission_tx: mpsc::Sender<Submission>,
+    share_log: Arc<ShareLog>,
+    stats: Arc<ShareStats>,
+    template: Option<Template>,
+    /// Threads this session should have running under the current target.
+    wanted: u64,
+}
+
+/// A booted serf owned by the mining loop. Retiring threads have had their
+/// attempt cancelled and are dropped as soon as it returns.
+struct MiningThread {
+    session: usize,
+    cancel_token: NockCancelToken,
+    retiring: bool,
+}
+
+type MiningAttempts = tokio::task::JoinSet<(
+    SerfThread<SaveableCheckpoint>,
+    u64,
+    Template,
+    Result<NounSlab>,
+    Span,
+)>;
+
+/// The serfs the mining loop is running and their in-flight attempts.
+/// Threads can be added and retired at any time; a retired thread's attempt
+/// is cancelled and its serf dropped when the attempt returns.
+struct MiningThreads {
+    threads: BTreeMap<u64, MiningThread>,
+    attempts: MiningAttempts,
+    next_id: u64,
+}
+
+impl MiningThreads {
+    fn new() -> Self {
+        Self {
+            threads: BTreeMap::new(),
+            attempts: MiningAttempts::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Starts mining `template` for `session` on a freshly booted serf.
+    async fn add(
+        &mut self,
+        serf: SerfThread<SaveableCheckpoint>,
+        session: usize,
+        template: &Template,
+        nonce_range: NonceRange,
+    ) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.threads.insert(id, MiningThread {
+            session,
+            cancel_token: serf.cancel_token.clone(),
+            retiring: false,
+        });
+        mine(serf, template, &mut self.attempts, None, id, nonce_range).await;
+        id
+    }
+
+    /// Cancels the thread's attempt; its serf is dropped once the attempt returns.
+    fn retire(&mut self, id: u64) {
+        if let Some(thread) = self.threads.get_mut(&id) {
+            thread.retiring = true;
+            thread.cancel_token.cancel();
+            info!("retiring mining thread={id}");
+        }
+    }
+
+    /// Ids of the threads mining for `session` that are not being retired.
+    fn active(&self, session: usize) -> Vec<u64> {
+        self.threads
+            .iter()
+            .filter(|(_, thread)| thread.session == session && !thread.retiring)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Cancels every attempt for `session` so it restarts on a new template.
+    /// Returns how many threads were cancelled.
+    fn cancel_session(&self, session: usize) -> usize {
+        let running: Vec<&MiningThread> = self.threads.values().filter(|thread| thread.session == session).collect();
+        for thread in &running {
+            thread.cancel_token.cancel();
+        }
+        running.len()
+    }
+
+    async fn next_result(&mut self) -> Option<(SerfThread<SaveableCheckpoint>, u64, Template, Result<NounSlab>, Span)> {
+        let mining_result = self.attempts.join_next().await?;
+        Some(mining_result.expect("Mining attempt failed"))
+    }
+
+    /// Session of thread `id`, and whether it was being retired, in which
+    /// case it has now been forgotten.
+    fn finish(&mut self, id: u64) -> (usize, bool) {
+        let thread = self.threads.get(&id).expect("Mining attempt on unknown thread");
+        let session = thread.session;
+        if thread.retiring {
+            self.threads.remove(&id);
+            (session, true)
         } else {
-            calculated_threads as u64
+            (session, false)
         }
-    };
-    info!("mining with {} threads", num_threads);
+    }
+}
+
+/// Shares `target` threads between the sessions by weight.
+fn reallocate(states: &mut [SessionState], target: u64, weights: &[u32]) {
+    for (state, wanted) in states.iter_mut().zip(allocate_threads(target, weights)) {
+        state.wanted = wanted;
+    }
+}
 
-    let mut mining_attempts = tokio::task::JoinSet::<(
-        SerfThread<SaveableCheckpoint>,
-        u64,
-        Result<NounSlab, anyhow::Error>,
-    )>::new();
+/// Splits `total` threads across `weights` using the largest remainder
+/// method, so the counts always add up to `total`.
+pub fn allocate_threads(total: u64, weights: &[u32]) -> Vec<u64> {
+    let weight_sum: u64 = weights.iter().map(|w| *w as u64).sum();
+    if weight_sum == 0 {
+        return vec![0; weights.len()];
+    }
+
+    let mut allocation: Vec<u64> = weights.iter().map(|w| total * *w as u64 / weight_sum).collect();
+    let mut remainders: Vec<(u64, usize)> = weights
+        .iter()
+        .enumerate()
+        .map(|(i, w)| (total * *w as u64 % weight_sum, i))
+        .collect();
+    remainders.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+    let assigned: u64 = allocation.iter().sum();
+    for (_, i) in remainders.into_iter().take((total - assigned) as usize) {
+        allocation[i] += 1;
+    }
+    allocation
+}
 
-    let network_only = config.network_only;
+pub async fn start(
+    sessions: Vec<MiningSession>,
+    nonce_range_rx: watch::Receiver<NonceRange>,
+    mut thread_target: watch::Receiver<u64>,
+    status: Arc<MinerStatus>,
+) -> Result<()> {
+    let num_threads = *thread_target.borrow_and_update();
+    info!("mining with {} threads", num_threads);
 
-    if network_only {
+    if status.network_only() {
         info!("mining for network target only");
     } else {
         info!("mining for pool and network targets");
@@ -60,118 +239,236 @@ pub async fn start(
     let test_jets_str = std::env::var("NOCK_TEST_JETS").unwrap_or_default();
     let test_jets = nockapp::kernel::boot::parse_test_jets(test_jets_str.as_str());
 
-    let mining_data: Mutex<Option<Template>> = Mutex::new(None);
-    let mut cancel_tokens: Vec<NockCancelToken> = Vec::<NockCancelToken>::new();
-
-    loop {
-        tokio::select! {
-            mining_result = mining_attempts.join_next(), if !mining_attempts.is_empty() => {
-                let mining_result = mining_result.expect("Mining attempt failed");
-                let (serf, id, slab_res) = mining_result.expect("Mining attempt result failed");
-                let slab = slab_res.expect("Mining attempt result failed");
-
-                let result = unsafe { slab.root() };
-                let result_cell = result.as_cell().expect("Expected result to be a cell");
-
-                let hed = result_cell.head();
+    // Funnel every session's template updates into a single channel. A
+    // session whose template channel closes has ended for good, which is
+    // sent as `None`.
+    let mut weights: Vec<u32> = sessions.iter().map(|session| session.weight).collect();
+    let allocation = allocate_threads(num_threads, &weights);
+    let (template_update_tx, mut template_updates) = mpsc::unbounded_channel::<(usize, Option<Template>)>();
+    let mut states = Vec::with_capacity(sessions.len());
+
+    for (index, (session, threads)) in sessions.into_iter().zip(allocation).enumerate() {
+        if let Some(label) = &session.label {
+            info!("key={} gets {} of {} threads (weight {})", label, threads, num_threads, session.weight);
+        }
 
-                if hed.is_atom() && hed.eq_bytes("poke") {
-                    //  mining attempt was cancelled. restart with current block header.
-                    info!("using new template on thread={id}");
-                    mine(serf, mining_data.lock().await, &mut mining_attempts, None, id).await;
-                    continue;
-                } 
+        let mut template_rx = session.template_rx;
+        let template_update_tx = template_update_tx.clone();
+        tokio::spawn(async move {
+            while template_rx.changed().await.is_ok() {
+                let template = template_rx.borrow_and_update().clone();
+                if template_update_tx.send((index, Some(template))).is_err() {
+                    return;
+                }
+            }
+            let _ = template_update_tx.send((index, None));
+        });
+
+        states.push(SessionState {
+            label: session.label,
+            submission_tx: session.submission_tx,
+            share_log: session.share_log,
+            stats: session.stats,
+            template: None,
+            wanted: threads,
+        });
+    }
+    drop(template_update_tx);
 
-                let effect = hed.as_cell().expect("Expected result to be a cell");
+    let mut threads = MiningThreads::new();
 
-                if effect.head().eq_bytes("miss") {
-                    info!("solution did not hit targets on thread={id}, trying again");
-                    let mut nonce_slab = NounSlab::new();
-                    nonce_slab.copy_into(effect.tail());
-                    mine(serf, mining_data.lock().await, &mut mining_attempts, Some(nonce_slab), id).await;
-                    continue;
-                }
+    loop {
+        let rebalance = tokio::select! {
+            Some((serf, id, template, slab_res, span)) = threads.next_result() => {
+                let slab = slab_res.expect("Mining attempt result failed");
 
-                let target_type = if effect.head().eq_bytes("pool") {
-                    Target::Pool
-                } else if effect.head().eq_bytes("network") {
-                    Target::Network
+                let (index, retired) = threads.finish(id);
+                let state = &states[index];
+                let (result, nonce) = span.in_scope(|| process_result(&slab, id, &template, status.network_only(), state))?;
+                span.record("outcome", result.as_str());
+                drop(span);
+                status.attempt_finished(id, result);
+
+                if retired {
+                    // Dropping the serf shuts its thread down.
+                    drop(serf);
+                    status.thread_retired(id);
+                    info!("retired mining thread={id}");
                 } else {
-                    info!("solution found but invalid target: {:?}", effect.head());
-                    mine(serf, mining_data.lock().await, &mut mining_attempts, None, id).await;
-                    continue;
-                };
-
-                if network_only && target_type != Target::Network {
-                    info!("solution did not hit network target on thread={id}, trying again");
-                    mine(serf, mining_data.lock().await, &mut mining_attempts, None, id).await;
-                    continue;
+                    let current = state.template.as_ref().expect("Mining data should already be initialized");
+                    let nonce_range = *nonce_range_rx.borrow();
+                    status.attempt_started(id, state.label.as_ref());
+                    mine(serf, current, &mut threads.attempts, nonce, id, nonce_range).await;
+                }
+                false
+            }
+            Some((index, update)) = template_updates.recv() => {
+                let session = states[index].label.as_ref().map(|label| format!(" for key={}", label)).unwrap_or_default();
+                match update {
+                    Some(template) => {
+                        let state = &mut states[index];
+                        let first_template = state.template.is_none();
+                        state.template = Some(template);
+                        status.template_received();
+
+                        if first_template {
+                            info!("Received nockpool template{}! Starting {} mining threads", session, state.wanted);
+                        } else {
+                            // Mining is already running so cancel all the running attemps
+                            // which are mining on the old block.
+                            let cancelled = threads.cancel_session(index);
+                            info!("New nockpool template{}! Restarting {} mining threads", session, cancelled);
+                        }
+                        first_template
+                    }
+                    None => {
+                        // Its threads go to the sessions still running.
+                        weights[index] = 0;
+                        reallocate(&mut states, *thread_target.borrow(), &weights);
+                        info!("Pool session{} ended; moving its threads to the other keys", session);
+                        true
+                    }
                 }
+            }
+            Ok(()) = thread_target.changed() => {
+                let target = *thread_target.borrow_and_update();
+                reallocate(&mut states, target, &weights);
+                true
+            }
+        };
+
+        if !rebalance {
+            continue;
+        }
+
+        // Boot or retire serfs until every session with a template runs the
+        // number of threads it is allotted.
+        for (index, state) in states.iter().enumerate() {
+            let Some(current) = state.template.as_ref() else {
+                continue;
+            };
+            let active = threads.active(index);
 
-                let success_message = effect.tail().as_cell().expect("Expected result to be a cell");
+            for id in active.iter().rev().take(active.len().saturating_sub(state.wanted as usize)) {
+                threads.retire(*id);
+            }
 
-                // 2
-                let mut commit_slab: NounSlab = NounSlab::new();
-                commit_slab.copy_into(success_message.head());
-                let commit = commit_slab.jam();
+            for _ in active.len() as u64..state.wanted {
+                let kernel = Vec::from(KERNEL);
+                let serf = SerfThread::<SaveableCheckpoint>::new(
+                    kernel,
+                    None,
+                    hot_state.clone(),
+                    NOCK_STACK_SIZE_TINY,
+                    test_jets.clone(),
+                    false,
+                )
+                .await
+                .expect("Could not load mining kernel");
+
+                let nonce_range = *nonce_range_rx.borrow();
+                let id = threads.add(serf, index, current, nonce_range).await;
+                status.attempt_started(id, state.label.as_ref());
+            }
+        }
+    }
+}
 
-                // 3
-                let success_message_tail = success_message.tail().as_cell().expect("Expected result to be a cell");
+/// Handles the effect of a finished mining attempt, submitting any solution
+/// found. Returns how the attempt ended and the nonce to continue from when
+/// it missed.
+fn process_result(
+    slab: &NounSlab,
+    id: u64,
+    template: &Template,
+    network_only: bool,
+    session: &SessionState,
+) -> Result<(AttemptResult, Option<NounSlab>)> {
+    let result = unsafe { slab.root() };
+    let result_cell = result.as_cell().expect("Expected result to be a cell");
+
+    let hed = result_cell.head();
+
+    if hed.is_atom() && hed.eq_bytes("poke") {
+        //  mining attempt was cancelled. restart with current block header.
+        info!("using new template on thread={id}");
+        return Ok((AttemptResult::Cancelled, None));
+    }
 
-                // 6
-                let digest = Bytes::from(success_message_tail.head().as_atom()?.to_le_bytes());
+    let effect = hed.as_cell().expect("Expected result to be a cell");
 
-                // 7
-                let mut proof_slab: NounSlab = NounSlab::new();
-                proof_slab.copy_into(success_message_tail.tail());
-                let proof = proof_slab.jam();
+    if effect.head().eq_bytes("miss") {
+        info!("solution did not hit targets on thread={id}, trying again");
+        let mut nonce_slab = NounSlab::new();
+        nonce_slab.copy_into(effect.tail());
+        return Ok((AttemptResult::Miss, Some(nonce_slab)));
+    }
 
-                let submission = Submission::new(target_type.clone(), commit, digest, proof);
-                info!(
-                    "solution found on thread={id} for target={:?}. Proof size: {:?} KB. Submitting to nockpool.",
-                    target_type,
-                    ((submission.proof.len() as f64) / 1024.0 * 100.0).round() / 100.0,
-                );
-                submission_tx.send(submission).expect("Failed to send submission");
+    let (target_type, result) = if effect.head().eq_bytes("pool") {
+        (Target::Pool, AttemptResult::Pool)
+    } else if effect.head().eq_bytes("network") {
+        (Target::Network, AttemptResult::Network)
+    } else {
+        info!("solution found but invalid target: {:?}", effect.head());
+        return Ok((AttemptResult::Invalid, None));
+    };
 
-                mine(serf, mining_data.lock().await, &mut mining_attempts, None, id).await;
-            }
-            _ = template_rx.changed() => {
-                let template = template_rx.borrow_and_update().clone();
+    if network_only && target_type != Target::Network {
+        info!("solution did not hit network target on thread={id}, trying again");
+        // Not submitted, so it doesn't count as a pool hit.
+        return Ok((AttemptResult::Miss, None));
+    }
 
-                *(mining_data.lock().await) = Some(template);
-
-                if mining_attempts.is_empty() {
-                    for i in 0..num_threads {
-                        let kernel = Vec::from(KERNEL);
-                        let serf = SerfThread::<SaveableCheckpoint>::new(
-                            kernel,
-                            None,
-                            hot_state.clone(),
-                            NOCK_STACK_SIZE_TINY,
-                            test_jets.clone(),
-                            false,
-                        )
-                        .await
-                        .expect("Could not load mining kernel");
-
-                        cancel_tokens.push(serf.cancel_token.clone());
-
-                        mine(serf, mining_data.lock().await, &mut mining_attempts, None, i).await;
-                    }
-                    info!("Received nockpool template! Starting {} mining threads", num_threads);
-                } else {
-                    // Mining is already running so cancel all the running attemps
-                    // which are mining on the old block.
-                    info!("New nockpool template! Restarting {} mining threads", num_threads);
-                    for token in &cancel_tokens {
-                        token.cancel();
-                    }
-                }
-            },
-        }
+    let success_message = effect.tail().as_cell().expect("Expected result to be a cell");
+
+    // 2
+    let mut commit_slab: NounSlab = NounSlab::new();
+    commit_slab.copy_into(success_message.head());
+    let commit = commit_slab.jam();
+
+    // 3
+    let success_message_tail = success_message.tail().as_cell().expect("Expected result to be a cell");
+
+    // 6
+    let digest = Bytes::from(success_message_tail.head().as_atom()?.to_le_bytes());
+
+    // 7
+    let mut proof_slab: NounSlab = NounSlab::new();
+    proof_slab.copy_into(success_message_tail.tail());
+    let proof = proof_slab.jam();
+
+    let submission = Submission::new(target_type.clone(), commit, digest, proof);
+    info!(
+        "solution found on thread={id} for target={:?}. Proof size: {:?} KB. Submitting to nockpool.",
+        target_type,
+        ((submission.proof.len() as f64) / 1024.0 * 100.0).round() / 100.0,
+    );
+    let mut share = ShareContext::new(id, template, &submission);
+    share.session = session.label.clone();
+    // The share gets a trace of its own, linked to the attempt that found it,
+    // which the submission and the pool's verdict are recorded under.
+    let share_span = info_span!(
+        parent: None,
+        "share",
+        thread = id,
+        commit = %share_log::commit_id(&template.commit),
+        target = %share.target,
+        digest = %share.digest,
+    );
+    share_span.follows_from(&Span::current());
+    share.span = Some(share_span);
+    session.share_log.record_found(share);
+    session.stats.record_found();
+    match session.submission_tx.try_send(submission) {
+        Ok(()) => {}
+        Err(mpsc::error::TrySendError::Full(_)) => warn!("submission queue full, dropping share from thread={id}"),
+        // The session ended while this attempt was running.
+        Err(mpsc::error::TrySendError::Closed(_)) => warn!("pool session closed, dropping share from thread={id}"),
     }
+
+    Ok((result, None))
 }
+
 /*
         %template
         version=?(%0 %1 %2)
@@ -183,23 +480,21 @@ pub async fn start(
 */
 async fn mine(
     serf: SerfThread<SaveableCheckpoint>,
-    template: tokio::sync::MutexGuard<'_, Option<Template>>,
-    mining_attempts: &mut tokio::task::JoinSet<(
-        SerfThread<SaveableCheckpoint>,
-        u64,
-        Result<NounSlab>,
-    )>,
+    template: &Template,
+    mining_attempts: &mut MiningAttempts,
     nonce: Option<NounSlab>,
     id: u64,
+    nonce_range: NonceRange,
 ) {
     let mut slab = NounSlab::new();
+    let nonce_origin = if nonce.is_some() { "miss" } else { "fresh" };
     // let's first deal with the nonce
     let nonce = if let Some(nonce) = nonce {
         nonce
     } else {
         let mut rng = rand::thread_rng();
         let mut nonce_slab: NounSlab = NounSlab::new();
-        let mut nonce_cell = Atom::from_value(&mut nonce_slab, rng.gen::<u64>() % PRIME)
+        let mut nonce_cell = Atom::from_value(&mut nonce_slab, nonce_range.sample(&mut rng))
             .expect("Failed to create nonce atom")
             .as_noun();
         for _ in 1..5 {
@@ -213,7 +508,7 @@ async fn mine(
     };
 
     // now we deal with the rest of the template noun
-    let template_ref = template.as_ref().expect("Mining data should already be initialized");
+    let template_ref = template;
 
     let version_atom = Atom::from_bytes(&mut slab, (&template_ref.version.clone()).into());
     let commit = slab.cue_into(template_ref.commit.clone().into()).expect("Failed to cue commit");
@@ -233,12 +528,27 @@ async fn mine(
 
     slab.set_root(noun);
 
+    let template = template_ref.clone();
     let wire = WireRepr::new("miner", 1, vec![WireTag::String("candidate".to_string())]);
-    mining_attempts.spawn(async move {
-        info!("starting mining attempt on thread={id}");
-        let result = serf.poke(wire.clone(), slab.clone()).await.map_err(|e| anyhow::anyhow!(e));
-        (serf, id, result)
-    });
+    let span = info_span!(
+        "mining_attempt",
+        thread = id,
+        commit = %share_log::commit_id(&template.commit),
+        nonce_origin = nonce_origin,
+        duration_ms = field::Empty,
+        outcome = field::Empty,
+    );
+    let attempt_span = span.clone();
+    mining_attempts.spawn(
+        async move {
+            info!("starting mining attempt on thread={id}");
+            let started = Instant::now();
+            let result = serf.poke(wire.clone(), slab.clone()).await.map_err(|e| anyhow::anyhow!(e));
+            attempt_span.record("duration_ms", started.elapsed().as_millis() as u64);
+            (serf, id, template, result, attempt_span)
+        }
+        .instrument(span),
+    );
 }
 
 pub async fn benchmark() -> Result<()> {
@@ -246,21 +556,15 @@ pub async fn benchmark() -> Result<()> {
     let test_jets_str = std::env::var("NOCK_TEST_JETS").unwrap_or_default();
     let test_jets = nockapp::kernel::boot::parse_test_jets(test_jets_str.as_str());
 
-    let mining_data: Mutex<Option<Template>> = Mutex::new(Some(
-        Template::new(
-            Bytes::from(hex::decode("0200000000000000").expect("Failed to decode version")),
-            Bytes::from(hex::decode("017ee86437eac9dbae690081199671e25cb54ce700ff8cdb259db500063b409e2aa64f968ec7ed801e75db735d82443707").expect("Failed to decode commit")),
-            Bytes::from(hex::decode("81177307ec6aacb01ef04b58dbf601823db967ef80ed5256e50304ab9031bb015f1c808d1d2058623e470ce8cdf54174c07f08c49a068e8f02").expect("Failed to decode network target")),
-            Bytes::from(hex::decode("81177307ec6aacb01ef04b58dbf601823db967ef80ed5256e50304ab9031bb015f1c808d1d2058623e470ce8cdf54174c07f08c49a068e8f02").expect("Failed to decode pool target")),
-            Bytes::from(hex::decode("4000000000000000").expect("Failed to decode pow len")),
-        )
-    ));
-
-    let mut mining_attempts = tokio::task::JoinSet::<(
-        SerfThread<SaveableCheckpoint>,
-        u64,
-        Result<NounSlab>,
-    )>::new();
+    let template = Template::new(
+        Bytes::from(hex::decode("0200000000000000").expect("Failed to decode version")),
+        Bytes::from(hex::decode("017ee86437eac9dbae690081199671e25cb54ce700ff8cdb259db500063b409e2aa64f968ec7ed801e75db735d82443707").expect("Failed to decode commit")),
+        Bytes::from(hex::decode("81177307ec6aacb01ef04b58dbf601823db967ef80ed5256e50304ab9031bb015f1c808d1d2058623e470ce8cdf54174c07f08c49a068e8f02").expect("Failed to decode network target")),
+        Bytes::from(hex::decode("81177307ec6aacb01ef04b58dbf601823db967ef80ed5256e50304ab9031bb015f1c808d1d2058623e470ce8cdf54174c07f08c49a068e8f02").expect("Failed to decode pool target")),
+        Bytes::from(hex::decode("4000000000000000").expect("Failed to decode pow len")),
+    );
+
+    let mut mining_attempts = MiningAttempts::new();
     let kernel = Vec::from(KERNEL);
     let serf = SerfThread::<SaveableCheckpoint>::new(
         kernel,
@@ -273,7 +577,7 @@ pub async fn benchmark() -> Result<()> {
     .await
     .expect("Could not load mining kernel");
     let start = tokio::time::Instant::now();
-    let _ = mine(serf, mining_data.lock().await, &mut mining_attempts, None, 1337).await;
+    let _ = mine(serf, &template, &mut mining_attempts, None, 1337, NonceRange::FULL).await;
 
     loop {
         tokio::select! {
@@ -285,4 +589,18 @@ pub async fn benchmark() -> Result<()> {
     let elapsed = start.elapsed();
     info!("Generated proof in {:?}", elapsed);
     return Ok(());
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocate_threads() {
+        assert_eq!(allocate_threads(12, &[1]), vec![12]);
+        assert_eq!(allocate_threads(12, &[3, 1]), vec![9, 3]);
+        assert_eq!(allocate_threads(10, &[1, 1, 1]), vec![4, 3, 3]);
+        assert_eq!(allocate_threads(1, &[1, 2]), vec![0, 1]);
+        assert_eq!(allocate_threads(5, &[0, 0]), vec![0, 0]);
+    }
+}
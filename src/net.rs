@@ -0,0 +1,84 @@
+use anyhow::{anyhow, Result};
+use reqwest::{Client, NoProxy, Proxy};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::ApiArgs;
+use crate::retry::RetryPolicy;
+use crate::tls::{self, CertPin};
+
+// Outbound networking settings shared by every HTTP client the miner builds.
+
+/// Environment variables consulted, in order, when no proxy is given on the command line.
+const PROXY_ENV_VARS: [&str; 4] = ["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"];
+
+#[derive(Clone, Debug, Default)]
+pub struct HttpOptions {
+    pub proxy: Option<String>,
+    pub ca_file: Option<PathBuf>,
+    pub pins: Vec<CertPin>,
+    /// Whole-request timeout; none when unset.
+    pub timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    /// How API calls that fail transiently are retried.
+    pub retry: RetryPolicy,
+}
+
+impl HttpOptions {
+    pub fn from_config(config: &ApiArgs) -> Result<Self> {
+        Ok(Self {
+            proxy: resolve_proxy(config.proxy.as_deref()),
+            ca_file: config.ca_file.clone(),
+            pins: config
+                .tls_pins
+                .iter()
+                .map(|pin| CertPin::parse(pin))
+                .collect::<Result<_>>()?,
+            timeout: Some(Duration::from_secs(config.api_timeout)),
+            connect_timeout: Some(Duration::from_secs(config.api_connect_timeout)),
+            retry: RetryPolicy {
+                max_retries: config.api_retries,
+                ..Default::default()
+            },
+        })
+    }
+}
+
+/// Picks the proxy to use: an explicit `--proxy` wins, then `HTTPS_PROXY`, then `ALL_PROXY`.
+pub fn resolve_proxy(explicit: Option<&str>) -> Option<String> {
+    explicit
+        .map(str::to_string)
+        .or_else(|| {
+            PROXY_ENV_VARS
+                .iter()
+                .find_map(|var| std::env::var(var).ok())
+        })
+        .map(|proxy| proxy.trim().to_string())
+        .filter(|proxy| !proxy.is_empty())
+}
+
+pub fn build_http_client(options: &HttpOptions) -> Result<Client> {
+    // Proxy environment variables are resolved by us, so reqwest's own
+    // lookup is disabled to keep the behaviour in one place.
+    let mut builder = Client::builder().no_proxy();
+
+    if let Some(proxy_url) = &options.proxy {
+        let proxy = Proxy::all(proxy_url)
+            .map_err(|e| anyhow!("Invalid proxy URL '{}': {}", proxy_url, e))?
+            .no_proxy(NoProxy::from_env());
+        builder = builder.proxy(proxy);
+    }
+
+    if options.ca_file.is_some() || !options.pins.is_empty() {
+        builder = builder.use_preconfigured_tls(tls::client_config(options.ca_file.as_deref(), &options.pins)?);
+    }
+
+    if let Some(timeout) = options.timeout {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(connect_timeout) = options.connect_timeout {
+        builder = builder.connect_timeout(connect_timeout);
+    }
+
+    Ok(builder.build()?)
+}
@@ -1,10 +1,13 @@
 use async_trait::async_trait;
 use tokio::sync::watch;
 use anyhow::Result;
+use tracing::{debug, info_span};
 
 use quiver::new_job::NewJobConsumer;
 use quiver::types::Template;
 
+use crate::share_log;
+
 #[derive(Clone, Debug)]
 pub struct NockPoolNewJobConsumer {
     pub template_tx: watch::Sender<Template>,
@@ -19,7 +22,11 @@ impl NockPoolNewJobConsumer {
 #[async_trait]
 impl NewJobConsumer for NockPoolNewJobConsumer {
     async fn process(&self, template: Template) -> Result<()> {
-        let _ = self.template_tx.send(template);
+        let span = info_span!("template_received", commit = %share_log::commit_id(&template.commit));
+        span.in_scope(|| {
+            debug!("new template received");
+            let _ = self.template_tx.send(template);
+        });
         Ok(())
     }
 }
@@ -0,0 +1,306 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use anyhow::{anyhow, Result};
+use quinn::ConnectionError;
+use tokio::sync::{broadcast, oneshot};
+use tracing::{error, info, warn};
+
+use quiver::device_info::DeviceInfo;
+
+use crate::key_manager::KeyManager;
+use crate::new_job::NockPoolNewJobConsumer;
+use crate::share_log::ShareLog;
+use crate::submission::{NockPoolSubmissionProvider, NockPoolSubmissionResponseHandler};
+use crate::status::{ConnectionState, MinerStatus};
+
+// A quiver client session against the pool, restarted with exponential
+// backoff whenever it ends, fails or panics. A key the pool refuses is
+// replaced once through the key manager; retrying it would never succeed,
+// so if that fails too the session ends with an error. Its threads go to the
+// other keys, and the miner stops once no session is left.
+// Rotating a key ahead of expiry asks for a reconnect, which switches to
+// the new key and revokes the old one.
+//
+// Each client runs on a thread of its own, so a panic restarts only its
+// session.
+
+/// Client threads are named this followed by the session name, which is how
+/// the panic hook tells which session panicked.
+pub const CLIENT_THREAD_PREFIX: &str = "quiver-";
+
+pub struct PoolSession {
+    /// Key label when mining for several keys at once.
+    pub label: Option<String>,
+    pub insecure: bool,
+    pub server_address: String,
+    pub client_address: String,
+    pub key: String,
+    pub device_info: DeviceInfo,
+    pub new_job_consumer: Arc<NockPoolNewJobConsumer>,
+    pub submission_provider: Arc<NockPoolSubmissionProvider>,
+    pub submission_response_handler: Arc<NockPoolSubmissionResponseHandler>,
+    /// Tracks the shares this session has submitted until the pool answers.
+    pub share_log: Arc<ShareLog>,
+    pub status: Arc<MinerStatus>,
+    /// Replaces the key if the pool rejects it; unset for keys we didn't create.
+    pub key_manager: Option<Arc<KeyManager>>,
+}
+
+/// Why pool sessions are being told to reconnect.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Reconnect {
+    /// The quiver client of the named session panicked inside one of its tasks.
+    Panic(String),
+    /// An operator asked for every session to reconnect, e.g. through the control API.
+    Requested,
+}
+
+/// A quiver client running on its own thread and runtime. Everything quiver
+/// spawns stays on that thread, and dropping this stops the client along
+/// with all of its tasks.
+struct ClientThread {
+    _stop: oneshot::Sender<()>,
+    done: oneshot::Receiver<Result<()>>,
+}
+
+impl ClientThread {
+    fn spawn(name: &str, session: &PoolSession, key: String) -> Self {
+        let (stop, stop_rx) = oneshot::channel();
+        let (done_tx, done) = oneshot::channel();
+        // quiver builds its own TLS configuration from `insecure` alone, so
+        // --ca-file and --tls-pin can't reach the pool connection until it
+        // takes a rustls config or verifier from us.
+        let client = quiver::client::run(
+            session.insecure,
+            session.server_address.clone(),
+            session.client_address.clone(),
+            key,
+            session.device_info.clone(),
+            session.new_job_consumer.clone(),
+            session.submission_provider.clone(),
+            session.submission_response_handler.clone(),
+        );
+        let thread_name = format!("{}{}", CLIENT_THREAD_PREFIX, name);
+        let spawned = std::thread::Builder::new()
+            .name(thread_name.clone())
+            .spawn(move || {
+                // Blocking tasks get the name too.
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .thread_name(thread_name)
+                    .enable_all()
+                    .build();
+                let runtime = match runtime {
+                    Ok(runtime) => runtime,
+                    Err(e) => {
+                        let _ = done_tx.send(Err(e.into()));
+                        return;
+                    }
+                };
+                runtime.block_on(async move {
+                    tokio::select! {
+                        result = client => {
+                            let _ = done_tx.send(result);
+                        }
+                        _ = stop_rx => {}
+                    }
+                });
+            });
+        // The dropped sender ends the session like a client that failed.
+        if let Err(e) = spawned {
+            error!("Failed to start the quiver client thread: {}", e);
+        }
+        Self { _stop: stop, done }
+    }
+}
+
+/// Reasons the pool gives when a mining key isn't accepted.
+const AUTH_FAILURE_MARKERS: &[&str] = &[
+    "unauthenticated",
+    "unauthorized",
+    "invalid key",
+    "invalid token",
+    "invalid mining key",
+    "revoked",
+    "key not found",
+];
+
+fn names_refused_key(reason: &str) -> bool {
+    let reason = reason.to_ascii_lowercase();
+    AUTH_FAILURE_MARKERS.iter().any(|marker| reason.contains(marker))
+}
+
+/// Whether a client error means the key itself was refused, as opposed to a
+/// network or server problem that a reconnect can fix. The pool refuses a key
+/// by closing the QUIC connection with a reason; timeouts, resets and other
+/// transport failures never mean that, whatever quiver says around them.
+pub fn is_auth_failure(error: &anyhow::Error) -> bool {
+    match error.chain().find_map(|cause| cause.downcast_ref::<ConnectionError>()) {
+        Some(ConnectionError::ApplicationClosed(close)) => names_refused_key(&String::from_utf8_lossy(&close.reason)),
+        Some(_) => false,
+        // Refused before a connection was up, so there is only quiver's message.
+        None => names_refused_key(&format!("{:#}", error)),
+    }
+}
+
+/// Every session binds its own local port: the `index`-th session uses the
+/// configured client port plus `index`.
+pub fn client_address_for(client_address: &str, index: usize) -> Result<String> {
+    if index == 0 {
+        return Ok(client_address.to_string());
+    }
+    let mut address: SocketAddr = client_address
+        .parse()
+        .map_err(|e| anyhow!("Invalid client address '{}': {}", client_address, e))?;
+    let port = address
+        .port()
+        .checked_add(index as u16)
+        .ok_or_else(|| anyhow!("Client port range starting at {} overflows", address.port()))?;
+    address.set_port(port);
+    Ok(address.to_string())
+}
+
+/// Runs the session until the pool refuses its key for good.
+pub async fn run(session: PoolSession, mut reconnect_rx: broadcast::Receiver<Reconnect>) -> Result<()> {
+    let mut backoff_ms = 100_u64;
+    let max_backoff_ms = 30_000_u64;
+    let for_key = session.label.as_ref().map(|label| format!(" for key={}", label)).unwrap_or_default();
+    let name = session.label.clone().unwrap_or_else(|| "pool".to_string());
+    let mut templates = session.new_job_consumer.template_tx.subscribe();
+    let mut key = session.key.clone();
+    // Set once a replacement key has been created, until it gets a template.
+    let mut replaced_key = false;
+
+    'session: loop {
+        // Verdicts for shares sent on the previous connection will never
+        // arrive; left queued they would be matched to the next shares.
+        session.share_log.abandon_in_flight();
+        session.submission_provider.discard_queued();
+        if let Some(key_manager) = &session.key_manager {
+            if let Some(current) = key_manager.current_key().filter(|current| *current != key) {
+                info!("Switching{} to the rotated mining key", for_key);
+                let retired = std::mem::replace(&mut key, current);
+                let key_manager = key_manager.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = key_manager.revoke_key(&retired).await {
+                        warn!("Failed to revoke the previous mining key: {:#}", e);
+                    }
+                });
+            }
+        }
+        session.status.set_connection(&name, ConnectionState::Connecting);
+        templates.mark_unchanged();
+
+        // Start the quiver client
+        let mut client = ClientThread::spawn(&name, &session, key.clone());
+
+        // Wait for either the client to finish or a panic to occur
+        let client_result = loop {
+            tokio::select! {
+                result = &mut client.done => {
+                    break Some(result);
+                }
+                reason = reconnect_rx.recv() => {
+                    match reason {
+                        Ok(Reconnect::Panic(panicked)) if panicked != name => continue,
+                        Ok(Reconnect::Panic(_)) => {
+                            error!("Panic detected in quiver client{} - triggering reconnection", for_key);
+                            break None;
+                        }
+                        Ok(Reconnect::Requested) => info!("Reconnect requested{}, restarting client", for_key),
+                        // Whatever was missed may have been meant for us.
+                        Err(broadcast::error::RecvError::Lagged(missed)) => {
+                            warn!("Missed {} reconnect requests{}, restarting client", missed, for_key);
+                        }
+                        // The panic hook holds a sender for as long as the process runs.
+                        Err(broadcast::error::RecvError::Closed) => continue,
+                    }
+                    backoff_ms = 100;
+                    continue 'session;
+                }
+                Ok(()) = templates.changed() => {
+                    session.status.set_connection(&name, ConnectionState::Connected);
+                    replaced_key = false;
+                }
+            }
+        };
+        // Stop whatever is left of a client that panicked.
+        drop(client);
+
+        match client_result {
+            Some(Ok(Ok(()))) => {
+                info!("Client connection{} completed successfully, reconnecting immediately", for_key);
+                backoff_ms = 100;
+                continue;
+            }
+            Some(Ok(Err(e))) if is_auth_failure(&e) => {
+                // The old key may have expired with its replacement waiting.
+                let rotated = session.key_manager.as_ref().and_then(|key_manager| key_manager.current_key());
+                if rotated.is_some_and(|current| current != key) {
+                    warn!("The pool rejected the previous mining key{}: {}", for_key, e);
+                    continue;
+                }
+                let Some(key_manager) = session.key_manager.as_ref().filter(|_| !replaced_key) else {
+                    return Err(anyhow!(
+                        "The pool rejected the mining key{}: {}. The key has been revoked or is invalid; create a new one or run with --account-token",
+                        for_key,
+                        e
+                    ));
+                };
+                warn!("The pool rejected the mining key{}: {}; creating a new one", for_key, e);
+                match key_manager.replace_revoked_key(&key).await {
+                    Ok(new_key) => {
+                        key = new_key;
+                        replaced_key = true;
+                        backoff_ms = 100;
+                        continue;
+                    }
+                    Err(e) => return Err(e.context(format!("Failed to replace the rejected mining key{}", for_key))),
+                }
+            }
+            Some(Ok(Err(e))) => {
+                error!("Client connection{} failed: {}", for_key, e);
+                info!("Sleeping for {}ms before reconnecting", backoff_ms);
+            }
+            Some(Err(_)) => {
+                error!("Client thread{} stopped without a result", for_key);
+                info!("Sleeping for {}ms before reconnecting after thread failure", backoff_ms);
+            }
+            None => {
+                // Panic was detected
+                info!("Sleeping for {}ms before reconnecting after panic", backoff_ms);
+            }
+        }
+        session.status.set_connection(&name, ConnectionState::Backoff { retry_in_ms: backoff_ms });
+        tokio::time::sleep(tokio::time::Duration::from_millis(backoff_ms)).await;
+        backoff_ms = (backoff_ms * 2).min(max_backoff_ms);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bytes::Bytes;
+    use quinn::{ApplicationClose, VarInt};
+
+    fn closed_by_pool(reason: &'static str) -> anyhow::Error {
+        let close = ApplicationClose { error_code: VarInt::from_u32(1), reason: Bytes::from_static(reason.as_bytes()) };
+        anyhow::Error::new(ConnectionError::ApplicationClosed(close)).context("failed to open submission stream")
+    }
+
+    #[test]
+    fn test_auth_failures_are_told_apart_from_network_errors() {
+        let refused = closed_by_pool("invalid key");
+        assert_eq!(format!("{:#}", refused), "failed to open submission stream: closed by peer: invalid key (code 1)");
+        assert!(is_auth_failure(&refused));
+        assert!(!is_auth_failure(&closed_by_pool("server restarting")));
+
+        // Only the pool's reason counts, not what is said around a transport failure.
+        let timed_out = anyhow::Error::new(ConnectionError::TimedOut).context("invalid key exchange");
+        assert!(!is_auth_failure(&timed_out));
+        assert!(!is_auth_failure(&anyhow::Error::new(ConnectionError::Reset)));
+
+        assert!(is_auth_failure(&anyhow!("mining token has been revoked")));
+        assert!(!is_auth_failure(&anyhow!("connection refused")));
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::warn;
+
+// Retries for NockPool API requests. Connection failures, timeouts, rate
+// limiting and gateway errors (502/503/504) are retried with exponential
+// backoff and jitter, so a fleet restarting at once doesn't hammer the API in
+// lockstep. A `Retry-After` from the server replaces the backoff; if it asks
+// for longer than the policy's longest delay the request gives up instead.
+
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt.
+    pub max_retries: u32,
+    /// Backoff before the first retry; doubles with every retry after that.
+    pub base_delay: Duration,
+    /// Longest wait between attempts, including one asked for with `Retry-After`.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Backoff before retry `attempt` (counting from zero): a random time
+    /// between half and all of `base_delay * 2^attempt`, capped at `max_delay`.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(1u32.checked_shl(attempt).unwrap_or(u32::MAX))
+            .min(self.max_delay);
+        let ceiling_ms = ceiling.as_millis() as u64;
+        Duration::from_millis(rand::thread_rng().gen_range(ceiling_ms / 2..=ceiling_ms))
+    }
+
+    /// How long to wait before retry `attempt`, or `None` when the server
+    /// asked for a longer wait than the policy allows.
+    fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        match retry_after {
+            Some(retry_after) if retry_after > self.max_delay => None,
+            Some(retry_after) => Some(retry_after),
+            None => Some(self.backoff(attempt)),
+        }
+    }
+}
+
+/// Statuses worth another try: rate limiting and gateways in trouble.
+pub fn is_transient_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+fn is_transient_error(error: &reqwest::Error) -> bool {
+    error.is_timeout() || error.is_connect() || error.is_request()
+}
+
+/// The wait a `Retry-After` header asks for, given as seconds or an HTTP date.
+fn retry_after(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some((at.with_timezone(&Utc) - now).to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Sends `request` under `policy`, retrying transient failures. The final
+/// response is returned whatever its status, for the caller to interpret.
+/// Requests must have buffered bodies so they can be sent again; `action`
+/// names the request in retry warnings.
+pub async fn send(policy: &RetryPolicy, request: RequestBuilder, action: &str) -> Result<Response> {
+    let mut attempt = 0;
+    loop {
+        let this_try = request.try_clone().expect("retried requests have buffered bodies");
+        let (delay, reason) = match this_try.send().await {
+            Ok(response) if !is_transient_status(response.status()) => return Ok(response),
+            Ok(response) => {
+                if attempt >= policy.max_retries {
+                    return Ok(response);
+                }
+                match policy.delay(attempt, retry_after(response.headers(), Utc::now())) {
+                    Some(delay) => (delay, response.status().to_string()),
+                    None => return Ok(response),
+                }
+            }
+            Err(e) if is_transient_error(&e) && attempt < policy.max_retries => (policy.backoff(attempt), e.to_string()),
+            Err(e) => return Err(e.into()),
+        };
+
+        attempt += 1;
+        warn!(
+            "Failed to {} ({}), retrying in {:.1}s (attempt {}/{})",
+            action,
+            reason,
+            delay.as_secs_f64(),
+            attempt + 1,
+            policy.max_retries + 1
+        );
+        sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_backoff_grows_with_jitter_up_to_the_cap() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+        };
+        for _ in 0..100 {
+            let first = policy.backoff(0);
+            assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+            let third = policy.backoff(2);
+            assert!(third >= Duration::from_millis(200) && third <= Duration::from_millis(400));
+            assert!(policy.backoff(40) <= Duration::from_secs(1));
+        }
+    }
+
+    #[test]
+    fn test_retry_after_is_honoured_or_refused() {
+        let now = DateTime::parse_from_rfc3339("2026-10-18T12:00:00Z").unwrap().with_timezone(&Utc);
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("7"));
+        assert_eq!(retry_after(&headers, now), Some(Duration::from_secs(7)));
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("Sun, 18 Oct 2026 12:00:20 GMT"));
+        assert_eq!(retry_after(&headers, now), Some(Duration::from_secs(20)));
+
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(0, Some(Duration::from_secs(20))), Some(Duration::from_secs(20)));
+        assert_eq!(policy.delay(0, Some(Duration::from_secs(3600))), None);
+    }
+}
@@ -0,0 +1,214 @@
+use chrono::{Datelike, Local, NaiveTime, Timelike, Weekday};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::governor::ThreadGovernor;
+
+// Time-of-day mining schedule. A schedule is a `;`-separated list of windows
+//
+//     [DAYS ]HH:MM-HH:MM=THREADS
+//
+// where DAYS is `*` (default), a day (`mon`), a range (`mon-fri`) or a
+// comma-separated list of those, and THREADS is an absolute count, a
+// percentage of the configured threads or `off`. Windows that end before
+// they start wrap past midnight, and the hours after midnight belong to the
+// day the window started on. The first window matching the current local
+// time wins; outside every window the miner runs at full power.
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Amount {
+    Threads(u64),
+    Percent(u64),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Window {
+    /// Bit `n` is set when the window applies on the day `n` days from Monday.
+    days: u8,
+    start: u32,
+    end: u32,
+    amount: Amount,
+}
+
+impl Window {
+    fn matches(&self, weekday: Weekday, minute: u32) -> bool {
+        let on = |day: Weekday| self.days & (1 << day.num_days_from_monday()) != 0;
+        if self.start <= self.end {
+            on(weekday) && self.start <= minute && minute < self.end
+        } else if minute >= self.start {
+            on(weekday)
+        } else {
+            // Past midnight, so the window opened the evening before.
+            minute < self.end && on(weekday.pred())
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    windows: Vec<Window>,
+}
+
+fn parse_weekday(day: &str) -> Result<u32, String> {
+    Weekday::from_str(day)
+        .map(|day| day.num_days_from_monday())
+        .map_err(|_| format!("unknown day '{}'", day))
+}
+
+fn parse_days(days: &str) -> Result<u8, String> {
+    if days == "*" {
+        return Ok(0x7f);
+    }
+    let mut mask = 0u8;
+    for part in days.split(',') {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (parse_weekday(first)?, parse_weekday(last)?),
+            None => {
+                let day = parse_weekday(part)?;
+                (day, day)
+            }
+        };
+        let mut day = first;
+        loop {
+            mask |= 1 << day;
+            if day == last {
+                break;
+            }
+            day = (day + 1) % 7;
+        }
+    }
+    Ok(mask)
+}
+
+/// Minutes since midnight; `24:00` is allowed as an end of day.
+fn parse_minute(time: &str) -> Result<u32, String> {
+    if time == "24:00" {
+        return Ok(24 * 60);
+    }
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .map(|time| time.hour() * 60 + time.minute())
+        .map_err(|_| format!("invalid time '{}'", time))
+}
+
+fn parse_amount(amount: &str) -> Result<Amount, String> {
+    if amount.eq_ignore_ascii_case("off") {
+        return Ok(Amount::Threads(0));
+    }
+    if let Some(percent) = amount.strip_suffix('%') {
+        let percent = percent.parse::<u64>().map_err(|_| format!("invalid percentage '{}'", amount))?;
+        if percent > 100 {
+            return Err(format!("percentage '{}' is over 100%", amount));
+        }
+        return Ok(Amount::Percent(percent));
+    }
+    amount
+        .parse::<u64>()
+        .map(Amount::Threads)
+        .map_err(|_| format!("invalid thread count '{}'", amount))
+}
+
+fn parse_window(window: &str) -> Result<Window, String> {
+    let (days, rest) = match window.split_once(char::is_whitespace) {
+        Some((days, rest)) => (parse_days(&days.to_ascii_lowercase())?, rest.trim()),
+        None => (0x7f, window),
+    };
+    let (times, amount) = rest
+        .split_once('=')
+        .ok_or_else(|| format!("window '{}' is missing '=THREADS'", window))?;
+    let (start, end) = times
+        .split_once('-')
+        .ok_or_else(|| format!("window '{}' is missing 'HH:MM-HH:MM'", window))?;
+
+    Ok(Window {
+        days,
+        start: parse_minute(start.trim())?,
+        end: parse_minute(end.trim())?,
+        amount: parse_amount(amount.trim())?,
+    })
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let windows = s
+            .split(';')
+            .map(str::trim)
+            .filter(|window| !window.is_empty())
+            .map(parse_window)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Invalid schedule: {}", e))?;
+        if windows.is_empty() {
+            return Err("Invalid schedule: no windows".to_string());
+        }
+        Ok(Self { windows })
+    }
+}
+
+impl Schedule {
+    /// Thread cap at the given local day and time, or `None` outside every window.
+    fn cap_at(&self, weekday: Weekday, minute: u32, max_threads: u64) -> Option<u64> {
+        let window = self.windows.iter().find(|window| window.matches(weekday, minute))?;
+        Some(match window.amount {
+            Amount::Threads(threads) => threads.min(max_threads),
+            Amount::Percent(percent) => max_threads * percent / 100,
+        })
+    }
+
+    /// Applies the schedule to `governor` now and whenever a window boundary may have passed.
+    pub fn spawn(self, governor: Arc<ThreadGovernor>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                let now = Local::now();
+                let minute = now.hour() * 60 + now.minute();
+                let cap = self.cap_at(now.weekday(), minute, governor.max_threads());
+                governor.set_cap("schedule", cap);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> u32 {
+        parse_minute(time).unwrap()
+    }
+
+    #[test]
+    fn test_schedule_windows() {
+        let schedule: Schedule = "mon-fri 07:00-17:00=25%; mon-fri 17:00-21:00=off; sat,sun 22:00-06:00=4"
+            .parse()
+            .unwrap();
+
+        assert_eq!(schedule.cap_at(Weekday::Tue, at("06:59"), 16), None);
+        assert_eq!(schedule.cap_at(Weekday::Tue, at("07:00"), 16), Some(4));
+        assert_eq!(schedule.cap_at(Weekday::Fri, at("18:30"), 16), Some(0));
+        assert_eq!(schedule.cap_at(Weekday::Sat, at("12:00"), 16), None);
+        assert_eq!(schedule.cap_at(Weekday::Sun, at("23:15"), 16), Some(4));
+        assert_eq!(schedule.cap_at(Weekday::Sun, at("05:59"), 2), Some(2));
+        assert_eq!(schedule.cap_at(Weekday::Sun, at("06:00"), 16), None);
+
+        // Friday night runs into Saturday morning; Sunday night is not scheduled.
+        let nights: Schedule = "mon-fri 22:00-06:00=off".parse().unwrap();
+        assert_eq!(nights.cap_at(Weekday::Sat, at("02:00"), 16), Some(0));
+        assert_eq!(nights.cap_at(Weekday::Mon, at("02:00"), 16), None);
+        assert_eq!(nights.cap_at(Weekday::Mon, at("23:00"), 16), Some(0));
+    }
+
+    #[test]
+    fn test_schedule_rejects_bad_windows() {
+        assert!("".parse::<Schedule>().is_err());
+        assert!("07:00-17:00".parse::<Schedule>().is_err());
+        assert!("07:00-25:00=4".parse::<Schedule>().is_err());
+        assert!("07:00-17:00=150%".parse::<Schedule>().is_err());
+        assert!("someday 07:00-17:00=4".parse::<Schedule>().is_err());
+        assert_eq!(parse_days("fri-mon").unwrap(), 0b111_0001);
+    }
+}
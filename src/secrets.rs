@@ -0,0 +1,155 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use zeroize::Zeroizing;
+
+// Keys and tokens on the command line end up in `ps` output and shell
+// history, so each can also be read from a file, from stdin with `-`, or from
+// the credentials systemd hands a service with `LoadCredential=`. Whatever
+// the source, the value is held as a `Secret`, which never prints itself.
+
+/// Directory systemd exposes a service's `LoadCredential=` files in.
+const CREDENTIALS_DIRECTORY: &str = "CREDENTIALS_DIRECTORY";
+
+static STDIN_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// A key, token or passphrase. `Debug` and `Display` print a placeholder,
+/// and the value is wiped from memory when dropped.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(Zeroizing<String>);
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Self(Zeroizing::new(value))
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+impl FromStr for Secret {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() {
+            return Err("must not be empty".to_string());
+        }
+        Ok(Self::new(s.to_string()))
+    }
+}
+
+/// The first line of a secret file, without its line ending.
+fn parse(contents: &str, source: &str) -> Result<Secret> {
+    let value = contents.lines().next().unwrap_or_default().trim();
+    if value.is_empty() {
+        bail!("{} is empty", source);
+    }
+    Ok(Secret::new(value.to_string()))
+}
+
+/// Where a secret read from `path` came from, for error messages.
+pub fn source(path: &Path) -> String {
+    if path == Path::new("-") {
+        "stdin".to_string()
+    } else {
+        path.display().to_string()
+    }
+}
+
+/// The whole of `path`, or of stdin when `path` is `-`. Only one secret can
+/// come from stdin.
+fn read_contents(path: &Path) -> Result<Zeroizing<String>> {
+    if path != Path::new("-") {
+        return Ok(Zeroizing::new(
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?,
+        ));
+    }
+
+    if STDIN_TAKEN.swap(true, Ordering::SeqCst) {
+        bail!("Only one secret can be read from stdin");
+    }
+    let mut contents = Zeroizing::new(String::new());
+    std::io::stdin()
+        .read_to_string(&mut contents)
+        .map_err(|e| anyhow!("Failed to read a secret from stdin: {}", e))?;
+    Ok(contents)
+}
+
+/// Reads a secret from `path`, or from stdin when `path` is `-`.
+pub fn read(path: &Path) -> Result<Secret> {
+    parse(&read_contents(path)?, &source(path))
+}
+
+/// Reads one secret per line, numbered from 1. Blank lines and `#` comments
+/// are skipped.
+pub fn read_lines(path: &Path) -> Result<Vec<(usize, Secret)>> {
+    let contents = read_contents(path)?;
+    let lines: Vec<_> = contents
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| (number, Secret::new(line.to_string())))
+        .collect();
+    if lines.is_empty() {
+        bail!("{} is empty", source(path));
+    }
+    Ok(lines)
+}
+
+/// The file holding systemd credential `name`, when running under
+/// `LoadCredential=` and the credential was loaded.
+pub fn credential_path(name: &str) -> Option<PathBuf> {
+    let path = PathBuf::from(std::env::var_os(CREDENTIALS_DIRECTORY)?).join(name);
+    path.exists().then_some(path)
+}
+
+/// The systemd credential `name`, when running under `LoadCredential=`.
+pub fn credential(name: &str) -> Result<Option<Secret>> {
+    credential_path(name).map(|path| read(&path)).transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secrets_stay_out_of_debug_output() {
+        let secret: Secret = "nock_s3cret".parse().unwrap();
+        assert_eq!(secret.expose(), "nock_s3cret");
+        assert!(!format!("{:?} {}", Some(&secret), secret).contains("s3cret"));
+        assert!("  ".parse::<Secret>().is_err());
+    }
+
+    #[test]
+    fn test_secret_files_keep_the_first_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("account-token");
+        std::fs::write(&path, "nockacct_abc\r\n").unwrap();
+        assert_eq!(read(&path).unwrap().expose(), "nockacct_abc");
+
+        std::fs::write(&path, "\n").unwrap();
+        assert!(read(&path).is_err());
+
+        std::fs::write(&path, "# label:key:weight\nops:nock_a:3\n\nresearch:nock_b:1\n").unwrap();
+        let lines = read_lines(&path).unwrap();
+        let lines: Vec<_> = lines.iter().map(|(number, secret)| (*number, secret.expose())).collect();
+        assert_eq!(lines, [(2, "ops:nock_a:3"), (4, "research:nock_b:1")]);
+    }
+}
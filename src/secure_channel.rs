@@ -0,0 +1,235 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305};
+use sha2::Sha256;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+use zeroize::Zeroizing;
+
+use crate::farm::{FrameSink, FrameSource, MAX_FRAME_LEN};
+use crate::kdf;
+
+// Encrypted transport between a coordinator and its remote workers.
+//
+// Both sides exchange ephemeral x25519 keys and derive one ChaCha20-Poly1305
+// key per direction with HKDF-SHA256, salted with the farm secret stretched
+// by Argon2id. The worker then sends an encrypted confirmation, and the
+// coordinator only answers with its own once the worker's opens. A peer
+// holding a different farm secret derives different keys and fails to open
+// it, so a successful handshake authenticates both ends. Someone posing as a
+// coordinator still learns enough to test guesses at the secret offline, and
+// the stretching is what makes each guess expensive.
+
+const PROTOCOL_LABEL: &[u8] = b"nockpool-farm-v1";
+const CONFIRMATION: &[u8] = b"nockpool-farm-v1 confirm";
+
+/// The farm secret stretched with Argon2id, derived once at startup rather
+/// than on every connection.
+#[derive(Clone)]
+pub struct FarmKey(Zeroizing<[u8; 32]>);
+
+impl FarmKey {
+    pub fn derive(secret: &[u8]) -> Result<Self> {
+        let key = kdf::stretch(secret, PROTOCOL_LABEL, kdf::DEFAULT_COST).context("failed to derive farm key")?;
+        Ok(Self(key))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Coordinator,
+    Worker,
+}
+
+pub struct SecureWriter {
+    inner: OwnedWriteHalf,
+    key: LessSafeKey,
+    counter: u64,
+}
+
+pub struct SecureReader {
+    inner: OwnedReadHalf,
+    key: LessSafeKey,
+    counter: u64,
+}
+
+/// Message counters double as AEAD nonces; keys are per-connection and per-direction.
+fn next_nonce(counter: &mut u64) -> Result<Nonce> {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&counter.to_be_bytes());
+    *counter = counter
+        .checked_add(1)
+        .ok_or_else(|| anyhow!("secure channel nonce exhausted"))?;
+    Ok(Nonce::assume_unique_for_key(nonce))
+}
+
+fn derive_key(farm_key: &FarmKey, shared: &[u8], transcript: &[u8], direction: &[u8]) -> Result<LessSafeKey> {
+    let hkdf = Hkdf::<Sha256>::new(Some(farm_key.0.as_ref()), shared);
+
+    let mut info = Vec::with_capacity(PROTOCOL_LABEL.len() + direction.len() + transcript.len());
+    info.extend_from_slice(PROTOCOL_LABEL);
+    info.extend_from_slice(direction);
+    info.extend_from_slice(transcript);
+
+    let mut key = [0u8; 32];
+    hkdf.expand(&info, &mut key)
+        .map_err(|_| anyhow!("failed to derive secure channel key"))?;
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &key)
+        .map_err(|_| anyhow!("failed to build secure channel key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Runs the handshake over `stream` and returns the encrypted halves.
+pub async fn handshake(mut stream: TcpStream, farm_key: &FarmKey, role: Role) -> Result<(SecureReader, SecureWriter)> {
+    let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+    let ours = PublicKey::from(&ephemeral);
+
+    stream.write_all(ours.as_bytes()).await?;
+    let mut theirs = [0u8; 32];
+    stream.read_exact(&mut theirs).await?;
+    let theirs = PublicKey::from(theirs);
+
+    let shared = ephemeral.diffie_hellman(&theirs);
+    if !shared.was_contributory() {
+        return Err(anyhow!("peer sent a low-order public key"));
+    }
+
+    let (worker_key, coordinator_key) = match role {
+        Role::Worker => (ours, theirs),
+        Role::Coordinator => (theirs, ours),
+    };
+    let mut transcript = Vec::with_capacity(64);
+    transcript.extend_from_slice(worker_key.as_bytes());
+    transcript.extend_from_slice(coordinator_key.as_bytes());
+
+    let to_coordinator = derive_key(farm_key, shared.as_bytes(), &transcript, b"worker->coordinator")?;
+    let to_worker = derive_key(farm_key, shared.as_bytes(), &transcript, b"coordinator->worker")?;
+    let (send_key, recv_key) = match role {
+        Role::Worker => (to_coordinator, to_worker),
+        Role::Coordinator => (to_worker, to_coordinator),
+    };
+
+    let (reader, writer) = stream.into_split();
+    let mut reader = SecureReader { inner: reader, key: recv_key, counter: 0 };
+    let mut writer = SecureWriter { inner: writer, key: send_key, counter: 0 };
+
+    // The coordinator says nothing under the farm key until the worker has
+    // proven it holds the secret.
+    if role == Role::Worker {
+        writer.send(CONFIRMATION).await?;
+    }
+    let confirmation = reader
+        .recv()
+        .await
+        .map_err(|_| anyhow!("handshake failed: peer does not share our farm secret"))?;
+    if confirmation != CONFIRMATION {
+        return Err(anyhow!("handshake failed: unexpected confirmation"));
+    }
+    if role == Role::Coordinator {
+        writer.send(CONFIRMATION).await?;
+    }
+
+    Ok((reader, writer))
+}
+
+#[async_trait]
+impl FrameSink for SecureWriter {
+    async fn send(&mut self, payload: &[u8]) -> Result<()> {
+        let nonce = next_nonce(&mut self.counter)?;
+        let mut sealed = payload.to_vec();
+        self.key
+            .seal_in_place_append_tag(nonce, Aad::empty(), &mut sealed)
+            .map_err(|_| anyhow!("failed to encrypt frame"))?;
+
+        self.inner.write_u32(sealed.len() as u32).await?;
+        self.inner.write_all(&sealed).await?;
+        self.inner.flush().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FrameSource for SecureReader {
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        let len = self.inner.read_u32().await? as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(anyhow!("frame of {} bytes exceeds limit", len));
+        }
+        let mut sealed = vec![0; len];
+        self.inner.read_exact(&mut sealed).await?;
+
+        let nonce = next_nonce(&mut self.counter)?;
+        let plaintext_len = self
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut sealed)
+            .map_err(|_| anyhow!("failed to decrypt frame"))?
+            .len();
+        sealed.truncate(plaintext_len);
+        Ok(sealed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    async fn connect_pair(worker_secret: &'static [u8], coordinator_secret: &'static [u8]) -> (Result<(SecureReader, SecureWriter)>, Result<(SecureReader, SecureWriter)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let coordinator = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            handshake(stream, &FarmKey::derive(coordinator_secret).unwrap(), Role::Coordinator).await
+        });
+        let stream = TcpStream::connect(address).await.unwrap();
+        let worker = handshake(stream, &FarmKey::derive(worker_secret).unwrap(), Role::Worker).await;
+        (worker, coordinator.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_handshake_and_frames() {
+        let (worker, coordinator) = connect_pair(b"farm secret", b"farm secret").await;
+        let (_, mut worker_tx) = worker.unwrap();
+        let (mut coordinator_rx, _) = coordinator.unwrap();
+
+        worker_tx.send(b"first").await.unwrap();
+        worker_tx.send(b"second").await.unwrap();
+        assert_eq!(coordinator_rx.recv().await.unwrap(), b"first");
+        assert_eq!(coordinator_rx.recv().await.unwrap(), b"second");
+    }
+
+    #[tokio::test]
+    async fn test_handshake_rejects_wrong_secret() {
+        let (worker, coordinator) = connect_pair(b"farm secret", b"other secret").await;
+        assert!(worker.is_err());
+        assert!(coordinator.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_coordinator_stays_silent_for_a_wrong_confirmation() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let coordinator = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            handshake(stream, &FarmKey::derive(b"farm secret").unwrap(), Role::Coordinator).await
+        });
+
+        // Exchange keys, then send a confirmation sealed under some other key.
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let ours = PublicKey::from(&EphemeralSecret::random_from_rng(OsRng));
+        stream.write_all(ours.as_bytes()).await.unwrap();
+        let mut theirs = [0u8; 32];
+        stream.read_exact(&mut theirs).await.unwrap();
+        stream.write_u32(CONFIRMATION.len() as u32 + 16).await.unwrap();
+        stream.write_all(&[0u8; CONFIRMATION.len() + 16]).await.unwrap();
+
+        assert!(coordinator.await.unwrap().is_err());
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+    }
+}
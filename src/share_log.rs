@@ -0,0 +1,297 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+use sha2::{Digest, Sha256};
+use tracing::{info, info_span, warn, Span};
+
+use quiver::types::{Submission, SubmissionResponse, Template};
+
+use crate::key_storage;
+
+// Forensics log for shares rejected by the pool. Every share found by the
+// miner is tracked from the moment the prover returns it until the pool
+// answers; rejections are appended to a JSONL file in the config directory
+// with the full template and timing context so they can be handed to the
+// pool operators.
+
+const REJECTED_SHARES_FILENAME: &str = "rejected_shares.jsonl";
+
+/// Everything we know about a share at the time it was found.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShareContext {
+    /// LAN miner that found the share when running in proxy mode. Optional
+    /// fields are always written: farm frames are bincode, which can't skip them.
+    #[serde(default)]
+    pub worker: Option<String>,
+    /// Proxy-local connection the share arrived on, used to route the verdict back.
+    #[serde(skip)]
+    pub connection: Option<u64>,
+    /// Label of the key the share was mined for when splitting across several keys.
+    #[serde(default)]
+    pub session: Option<String>,
+    pub thread_id: u64,
+    pub target: String,
+    pub version: String,
+    pub commit: String,
+    pub network_target: String,
+    pub pool_target: String,
+    pub pow_len: String,
+    pub digest: String,
+    pub proof_size: usize,
+    pub found_at: DateTime<Utc>,
+    /// Trace span the submission and the pool's verdict are recorded under.
+    #[serde(skip)]
+    pub span: Option<Span>,
+}
+
+impl ShareContext {
+    pub fn new(thread_id: u64, template: &Template, submission: &Submission) -> Self {
+        Self {
+            worker: None,
+            connection: None,
+            session: None,
+            thread_id,
+            target: format!("{:?}", submission.target).to_lowercase(),
+            version: hex::encode(&template.version),
+            commit: hex::encode(&template.commit),
+            network_target: hex::encode(&template.network_target),
+            pool_target: hex::encode(&template.pool_target),
+            pow_len: hex::encode(&template.pow_len),
+            digest: hex::encode(&submission.digest),
+            proof_size: submission.proof.len(),
+            found_at: Utc::now(),
+            span: None,
+        }
+    }
+}
+
+/// Short, stable identifier for a template commitment, used to correlate
+/// attempts, shares and template receipts in traces.
+pub fn commit_id(commit: &[u8]) -> String {
+    hex::encode(&Sha256::digest(commit)[..8])
+}
+
+#[derive(Debug, Serialize)]
+struct RejectedShare<'a> {
+    #[serde(flatten)]
+    share: &'a ShareContext,
+    submitted_at: Option<DateTime<Utc>>,
+    rejected_at: DateTime<Utc>,
+    reason: String,
+}
+
+/// The pool's verdict on a share; `rejection` holds the reason when it was rejected.
+#[derive(Clone, Debug)]
+pub struct ShareOutcome {
+    pub share: ShareContext,
+    pub rejection: Option<String>,
+}
+
+#[derive(Debug)]
+struct InFlight {
+    share: ShareContext,
+    submitted_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct ShareLog {
+    path: PathBuf,
+    found: Mutex<VecDeque<ShareContext>>,
+    in_flight: Mutex<VecDeque<InFlight>>,
+    outcomes: broadcast::Sender<ShareOutcome>,
+}
+
+impl ShareLog {
+    pub fn new() -> Result<Self> {
+        Ok(Self::with_path(key_storage::config_dir()?.join(REJECTED_SHARES_FILENAME)))
+    }
+
+    pub fn with_path(path: PathBuf) -> Self {
+        Self {
+            path,
+            found: Mutex::new(VecDeque::new()),
+            in_flight: Mutex::new(VecDeque::new()),
+            outcomes: broadcast::channel(64).0,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Every resolved share is published here, accepted or not.
+    pub fn subscribe(&self) -> broadcast::Receiver<ShareOutcome> {
+        self.outcomes.subscribe()
+    }
+
+    /// Called by the miner right before a share is handed to the submission channel.
+    pub fn record_found(&self, share: ShareContext) {
+        self.found.lock().expect("share log poisoned").push_back(share);
+    }
+
+    /// Called when a submission is picked up for sending. Shares found
+    /// before this one that were never sent are dropped here.
+    pub fn mark_submitted(&self, submission: &Submission) -> Option<ShareContext> {
+        let digest = hex::encode(&submission.digest);
+        let mut found = self.found.lock().expect("share log poisoned");
+        let pos = found.iter().position(|share| share.digest == digest)?;
+        let share = found.drain(..=pos).next_back().expect("drained range is non-empty");
+        self.in_flight.lock().expect("share log poisoned").push_back(InFlight {
+            share: share.clone(),
+            submitted_at: Utc::now(),
+        });
+        Some(share)
+    }
+
+    /// Forgets shares whose verdicts can no longer arrive, e.g. after the
+    /// connection they were sent on dropped.
+    pub fn abandon_in_flight(&self) {
+        let mut in_flight = self.in_flight.lock().expect("share log poisoned");
+        if !in_flight.is_empty() {
+            warn!("{} submitted shares will never get a verdict", in_flight.len());
+            in_flight.clear();
+        }
+    }
+
+    /// Matches a pool response to the oldest in-flight share and logs it if rejected.
+    pub fn record_response(&self, response: &SubmissionResponse) {
+        self.record_outcome(rejection_reason(response));
+    }
+
+    /// Resolves the oldest in-flight share with a verdict that did not come
+    /// straight from quiver, e.g. one relayed by a LAN proxy.
+    pub fn record_outcome(&self, rejection: Option<String>) {
+        let in_flight = self.in_flight.lock().expect("share log poisoned").pop_front();
+        let Some(InFlight { share, submitted_at }) = in_flight else {
+            if let Some(reason) = rejection {
+                warn!("pool rejected a share we have no record of: {}", reason);
+            }
+            return;
+        };
+
+        let parent = share.span.clone().unwrap_or_else(Span::none);
+        let _response = info_span!(
+            parent: &parent,
+            "submission_response",
+            accepted = rejection.is_none(),
+            reason = rejection.as_deref().unwrap_or(""),
+        )
+        .entered();
+
+        if let Some(reason) = &rejection {
+            if let Err(e) = self.append_rejection(&share, Some(submitted_at), reason.clone()) {
+                warn!("failed to write rejected share to {}: {}", self.path.display(), e);
+            } else {
+                info!("rejected share on thread={} logged to {}", share.thread_id, self.path.display());
+            }
+        }
+
+        // Nobody listening is fine.
+        let _ = self.outcomes.send(ShareOutcome { share, rejection });
+    }
+
+    fn append_rejection(&self, share: &ShareContext, submitted_at: Option<DateTime<Utc>>, reason: String) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let record = RejectedShare {
+            share,
+            submitted_at,
+            rejected_at: Utc::now(),
+            reason,
+        };
+        let mut line = serde_json::to_string(&record)?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+fn rejection_reason(response: &SubmissionResponse) -> Option<String> {
+    if response.accepted {
+        None
+    } else {
+        Some(response.message.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use quiver::types::Target;
+    use tempfile::tempdir;
+
+    fn share(digest: &str) -> ShareContext {
+        ShareContext {
+            worker: None,
+            connection: None,
+            session: None,
+            thread_id: 3,
+            target: "pool".to_string(),
+            version: "0200000000000000".to_string(),
+            commit: "01".to_string(),
+            network_target: "02".to_string(),
+            pool_target: "03".to_string(),
+            pow_len: "4000000000000000".to_string(),
+            digest: digest.to_string(),
+            proof_size: 1024,
+            found_at: Utc::now(),
+            span: None,
+        }
+    }
+
+    #[test]
+    fn test_rejected_share_lifecycle() {
+        let temp_dir = tempdir().unwrap();
+        let log = ShareLog::with_path(temp_dir.path().join(REJECTED_SHARES_FILENAME));
+
+        // Two shares found, only the second one is picked up by quiver.
+        log.record_found(share("aa"));
+        log.record_found(share("bb"));
+        let submission = Submission::new(Target::Pool, Bytes::new(), Bytes::from_static(&[0xbb]), Bytes::new());
+        log.mark_submitted(&submission);
+
+        assert!(log.found.lock().unwrap().is_empty());
+        let in_flight = log.in_flight.lock().unwrap().pop_front().unwrap();
+        assert_eq!(in_flight.share.digest, "bb");
+
+        log.append_rejection(&in_flight.share, Some(in_flight.submitted_at), "stale template".to_string()).unwrap();
+
+        let contents = std::fs::read_to_string(log.path()).unwrap();
+        let record: serde_json::Value = serde_json::from_str(contents.trim()).unwrap();
+        assert_eq!(record["digest"], "bb");
+        assert_eq!(record["thread_id"], 3);
+        assert_eq!(record["reason"], "stale template");
+    }
+
+    #[test]
+    fn test_verdicts_after_a_reconnect_match_new_shares() {
+        let temp_dir = tempdir().unwrap();
+        let log = ShareLog::with_path(temp_dir.path().join(REJECTED_SHARES_FILENAME));
+        let submit = |digest: u8| {
+            log.record_found(share(&hex::encode([digest])));
+            log.mark_submitted(&Submission::new(Target::Pool, Bytes::new(), Bytes::from(vec![digest]), Bytes::new()));
+        };
+
+        // "aa" was sent on a connection that dropped before the pool answered.
+        submit(0xaa);
+        log.abandon_in_flight();
+        submit(0xbb);
+        log.record_outcome(Some("duplicate share".to_string()));
+
+        let contents = std::fs::read_to_string(log.path()).unwrap();
+        let record: serde_json::Value = serde_json::from_str(contents.trim()).unwrap();
+        assert_eq!(record["digest"], "bb");
+        assert!(log.in_flight.lock().unwrap().is_empty());
+    }
+}
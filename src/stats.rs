@@ -0,0 +1,85 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tracing::info;
+
+use crate::share_log::ShareLog;
+
+// Share counters, kept per pool session so that miners splitting their
+// hardware across several keys can see what each key earned.
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Default)]
+pub struct ShareStats {
+    found: AtomicU64,
+    accepted: AtomicU64,
+    rejected: AtomicU64,
+}
+
+/// Point-in-time copy of a session's counters.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct ShareCounts {
+    pub found: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+}
+
+impl ShareStats {
+    pub fn record_found(&self) {
+        self.found.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts pool verdicts published by `share_log` for as long as it lives.
+    pub fn track(self: &Arc<Self>, share_log: &ShareLog) {
+        let stats = self.clone();
+        let mut outcomes = share_log.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match outcomes.recv().await {
+                    Ok(outcome) if outcome.rejection.is_none() => {
+                        stats.accepted.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Ok(_) => {
+                        stats.rejected.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    pub fn counts(&self) -> ShareCounts {
+        ShareCounts {
+            found: self.found.load(Ordering::Relaxed),
+            accepted: self.accepted.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let counts = self.counts();
+        format!(
+            "{} shares found, {} accepted, {} rejected",
+            counts.found, counts.accepted, counts.rejected,
+        )
+    }
+}
+
+/// Periodically logs the counters of every labelled session.
+pub fn spawn_reporter(sessions: Vec<(String, Arc<ShareStats>)>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(REPORT_INTERVAL);
+        // The first tick fires immediately, before anything has been mined.
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            for (label, stats) in &sessions {
+                info!("key={}: {}", label, stats.summary());
+            }
+        }
+    });
+}
@@ -0,0 +1,295 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+use crate::share_log::ShareLog;
+use crate::stats::{ShareCounts, ShareStats};
+
+// Live view of what the miner is doing, shared between the mining loop, the
+// pool sessions and whatever reports on them (the control API, the
+// dashboard, ...). Writers only ever take short locks; readers build a
+// `StatusSnapshot`.
+
+/// Proof rates are averaged over this window.
+const RATE_WINDOW: Duration = Duration::from_secs(600);
+
+/// How a mining attempt ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttemptResult {
+    Cancelled,
+    Miss,
+    Pool,
+    Network,
+    Invalid,
+}
+
+impl AttemptResult {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AttemptResult::Cancelled => "cancelled",
+            AttemptResult::Miss => "miss",
+            AttemptResult::Pool => "pool",
+            AttemptResult::Network => "network",
+            AttemptResult::Invalid => "invalid",
+        }
+    }
+}
+
+/// State of an upstream connection (a pool session or a LAN farm link).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum ConnectionState {
+    Connecting,
+    /// Up; pool sessions only count as connected once templates arrive.
+    Connected,
+    Backoff { retry_in_ms: u64 },
+}
+
+#[derive(Debug)]
+struct ThreadStatus {
+    session: Option<String>,
+    attempt_started: Instant,
+    attempts: u64,
+    last_result: Option<AttemptResult>,
+    last_duration: Option<Duration>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LastSubmission {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    pub at: DateTime<Utc>,
+    pub accepted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct MinerStatus {
+    started_at: Instant,
+    network_only: AtomicBool,
+    pool_hits: AtomicU64,
+    network_hits: AtomicU64,
+    threads: Mutex<BTreeMap<u64, ThreadStatus>>,
+    completions: Mutex<VecDeque<Instant>>,
+    last_template: Mutex<Option<Instant>>,
+    last_submission: Mutex<Option<LastSubmission>>,
+    sessions: Mutex<Vec<(Option<String>, Arc<ShareStats>)>>,
+    connections: Mutex<BTreeMap<String, (ConnectionState, Instant)>>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ThreadSnapshot {
+    pub id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    pub attempts: u64,
+    pub attempt_secs: f64,
+    pub last_result: Option<AttemptResult>,
+    pub last_attempt_secs: Option<f64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SessionSnapshot {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(flatten)]
+    pub shares: ShareCounts,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ConnectionSnapshot {
+    pub name: String,
+    #[serde(flatten)]
+    pub state: ConnectionState,
+    pub since_secs: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct StatusSnapshot {
+    pub uptime_secs: u64,
+    pub network_only: bool,
+    pub template_age_secs: Option<u64>,
+    pub proofs_per_minute: f64,
+    pub pool_hits: u64,
+    pub network_hits: u64,
+    pub threads: Vec<ThreadSnapshot>,
+    pub sessions: Vec<SessionSnapshot>,
+    pub connections: Vec<ConnectionSnapshot>,
+    pub last_submission: Option<LastSubmission>,
+}
+
+impl MinerStatus {
+    pub fn new(network_only: bool) -> Arc<Self> {
+        Arc::new(Self {
+            started_at: Instant::now(),
+            network_only: AtomicBool::new(network_only),
+            pool_hits: AtomicU64::new(0),
+            network_hits: AtomicU64::new(0),
+            threads: Mutex::new(BTreeMap::new()),
+            completions: Mutex::new(VecDeque::new()),
+            last_template: Mutex::new(None),
+            last_submission: Mutex::new(None),
+            sessions: Mutex::new(Vec::new()),
+            connections: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    pub fn network_only(&self) -> bool {
+        self.network_only.load(Ordering::Relaxed)
+    }
+
+    pub fn set_network_only(&self, network_only: bool) {
+        self.network_only.store(network_only, Ordering::Relaxed);
+    }
+
+    /// Registers a pool session's share counters and follows its verdicts.
+    pub fn add_session(self: &Arc<Self>, label: Option<String>, stats: Arc<ShareStats>, share_log: &ShareLog) {
+        self.sessions.lock().expect("status poisoned").push((label.clone(), stats));
+
+        let status = self.clone();
+        let mut outcomes = share_log.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match outcomes.recv().await {
+                    Ok(outcome) => {
+                        *status.last_submission.lock().expect("status poisoned") = Some(LastSubmission {
+                            session: label.clone(),
+                            at: Utc::now(),
+                            accepted: outcome.rejection.is_none(),
+                            reason: outcome.rejection,
+                        });
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    pub fn set_connection(&self, name: &str, state: ConnectionState) {
+        let mut connections = self.connections.lock().expect("status poisoned");
+        if connections.get(name).map(|(current, _)| *current) != Some(state) {
+            connections.insert(name.to_string(), (state, Instant::now()));
+        }
+    }
+
+    pub fn template_received(&self) {
+        *self.last_template.lock().expect("status poisoned") = Some(Instant::now());
+    }
+
+    pub fn attempt_started(&self, id: u64, session: Option<&String>) {
+        let mut threads = self.threads.lock().expect("status poisoned");
+        let thread = threads.entry(id).or_insert_with(|| ThreadStatus {
+            session: session.cloned(),
+            attempt_started: Instant::now(),
+            attempts: 0,
+            last_result: None,
+            last_duration: None,
+        });
+        thread.attempt_started = Instant::now();
+    }
+
+    pub fn attempt_finished(&self, id: u64, result: AttemptResult) {
+        let now = Instant::now();
+        if let Some(thread) = self.threads.lock().expect("status poisoned").get_mut(&id) {
+            thread.attempts += 1;
+            thread.last_result = Some(result);
+            thread.last_duration = Some(now - thread.attempt_started);
+        }
+        match result {
+            AttemptResult::Pool => {
+                self.pool_hits.fetch_add(1, Ordering::Relaxed);
+            }
+            AttemptResult::Network => {
+                self.network_hits.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+        if result != AttemptResult::Cancelled {
+            let mut completions = self.completions.lock().expect("status poisoned");
+            completions.push_back(now);
+            while completions.front().is_some_and(|at| now - *at > RATE_WINDOW) {
+                completions.pop_front();
+            }
+        }
+    }
+
+    pub fn thread_retired(&self, id: u64) {
+        self.threads.lock().expect("status poisoned").remove(&id);
+    }
+
+    pub fn snapshot(&self) -> StatusSnapshot {
+        let now = Instant::now();
+        let uptime = now - self.started_at;
+
+        let threads = self
+            .threads
+            .lock()
+            .expect("status poisoned")
+            .iter()
+            .map(|(id, thread)| ThreadSnapshot {
+                id: *id,
+                session: thread.session.clone(),
+                attempts: thread.attempts,
+                attempt_secs: (now - thread.attempt_started).as_secs_f64(),
+                last_result: thread.last_result,
+                last_attempt_secs: thread.last_duration.map(|duration| duration.as_secs_f64()),
+            })
+            .collect();
+
+        let completed = self
+            .completions
+            .lock()
+            .expect("status poisoned")
+            .iter()
+            .filter(|at| now - **at <= RATE_WINDOW)
+            .count();
+        let window = uptime.min(RATE_WINDOW).as_secs_f64().max(1.0);
+
+        let sessions = self
+            .sessions
+            .lock()
+            .expect("status poisoned")
+            .iter()
+            .map(|(label, stats)| SessionSnapshot {
+                label: label.clone(),
+                shares: stats.counts(),
+            })
+            .collect();
+
+        let connections = self
+            .connections
+            .lock()
+            .expect("status poisoned")
+            .iter()
+            .map(|(name, (state, since))| ConnectionSnapshot {
+                name: name.clone(),
+                state: *state,
+                since_secs: (now - *since).as_secs(),
+            })
+            .collect();
+
+        StatusSnapshot {
+            uptime_secs: uptime.as_secs(),
+            network_only: self.network_only(),
+            template_age_secs: self
+                .last_template
+                .lock()
+                .expect("status poisoned")
+                .map(|at| (now - at).as_secs()),
+            proofs_per_minute: completed as f64 * 60.0 / window,
+            pool_hits: self.pool_hits.load(Ordering::Relaxed),
+            network_hits: self.network_hits.load(Ordering::Relaxed),
+            threads,
+            sessions,
+            connections,
+            last_submission: self.last_submission.lock().expect("status poisoned").clone(),
+        }
+    }
+}
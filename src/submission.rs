@@ -1,21 +1,52 @@
 use async_trait::async_trait;
-use tokio::sync::{watch, Mutex};
-use anyhow::Result;
-use tracing::info;
+use tokio::sync::{mpsc, Mutex};
+use anyhow::{anyhow, Result};
+use tracing::{debug, info, info_span, warn, Span};
 use std::sync::Arc;
 
 use quiver::types::{Submission, SubmissionResponse};
 use quiver::submission::{SubmissionProvider, SubmissionResponseHandler};
 
+use crate::share_log::ShareLog;
+
+/// Shares waiting for the upstream connection. The old latest-only slot
+/// silently overwrote a share when two threads (or two LAN rigs) found one
+/// before the pool took the first; a short queue keeps both, and a full one
+/// means the connection is down, so further shares are dropped with a warning.
+pub const SUBMISSION_QUEUE: usize = 64;
+
+/// Throws away submissions queued before a reconnect. They were found on a
+/// template the new connection replaces, and their share contexts are
+/// cleared from the share log with the next submission.
+pub fn discard_queued(submission_rx: &mut mpsc::Receiver<Submission>) {
+    let mut discarded = 0;
+    while submission_rx.try_recv().is_ok() {
+        discarded += 1;
+    }
+    if discarded > 0 {
+        warn!("discarding {} shares queued before the reconnect", discarded);
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct NockPoolSubmissionProvider {
-    pub submission_rx: Arc<Mutex<watch::Receiver<Submission>>>,
+    pub submission_rx: Arc<Mutex<mpsc::Receiver<Submission>>>,
+    share_log: Arc<ShareLog>,
 }
 
 impl NockPoolSubmissionProvider {
-    pub fn new(submission_rx: watch::Receiver<Submission>) -> Self {
+    pub fn new(submission_rx: mpsc::Receiver<Submission>, share_log: Arc<ShareLog>) -> Self {
         Self {
             submission_rx: Arc::new(Mutex::new(submission_rx)),
+            share_log,
+        }
+    }
+
+    /// Skipped if a client that is still stopping holds the receiver; it
+    /// takes the next submission itself and drops it with its connection.
+    pub fn discard_queued(&self) {
+        if let Ok(mut submission_rx) = self.submission_rx.try_lock() {
+            discard_queued(&mut submission_rx);
         }
     }
 }
@@ -26,24 +57,31 @@ impl SubmissionProvider for NockPoolSubmissionProvider {
         // Lock the mutex to get exclusive access to the single, shared receiver.
         let mut guard = self.submission_rx.lock().await;
 
-        // Wait for the value to change from what the receiver has last seen.
-        // This mutates the guarded receiver's internal "seen" state.
-        // If the channel is closed, this will return an error.
-        guard.changed().await?;
-
-        // After a change has been received, borrow the new value and return it.
+        // Wait for the next queued submission (see `SUBMISSION_QUEUE`). If the
+        // channel is closed, this will return an error.
         // The mutex lock is released when `guard` goes out of scope here.
-        let submission = guard.borrow().clone();
+        let submission = guard
+            .recv()
+            .await
+            .ok_or_else(|| anyhow!("submission channel closed"))?;
+        if let Some(share) = self.share_log.mark_submitted(&submission) {
+            let parent = share.span.unwrap_or_else(Span::none);
+            info_span!(parent: &parent, "submission_send", digest = %share.digest, target = %share.target).in_scope(|| {
+                debug!("handing share on thread={} to quiver", share.thread_id);
+            });
+        }
         Ok(submission)
     }
 }
 
 #[derive(Clone, Debug)]
-pub struct NockPoolSubmissionResponseHandler {}
+pub struct NockPoolSubmissionResponseHandler {
+    share_log: Arc<ShareLog>,
+}
 
 impl NockPoolSubmissionResponseHandler {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(share_log: Arc<ShareLog>) -> Self {
+        Self { share_log }
     }
 }
 
@@ -51,6 +89,7 @@ impl NockPoolSubmissionResponseHandler {
 impl SubmissionResponseHandler for NockPoolSubmissionResponseHandler {
     async fn handle(&self, response: SubmissionResponse) -> Result<()> {
         info!("{:?}", response);
+        self.share_log.record_response(&response);
         Ok(())
     }
 }
@@ -0,0 +1,137 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use sysinfo::Components;
+use tracing::{info, warn};
+
+use crate::governor::ThreadGovernor;
+
+// Thermal governor. Polls the CPU package temperature and, whenever it is at
+// or above the configured limit, takes a quarter of the threads away. Threads
+// come back a quarter at a time once the package has cooled to the limit
+// minus the hysteresis, so the miner does not flap around the limit.
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+const SYSFS_THERMAL: &str = "/sys/class/thermal";
+
+/// sysinfo component labels that report the CPU package (or the closest thing to it).
+const CPU_LABELS: &[&str] = &["package id", "tctl", "tdie", "cpu", "coretemp", "k10temp", "zenpower"];
+
+/// `/sys/class/thermal` zone types that report the CPU.
+const CPU_ZONES: &[&str] = &["x86_pkg_temp", "cpu", "soc", "k10temp"];
+
+#[derive(Clone, Copy, Debug)]
+pub struct ThermalLimit {
+    pub limit: f32,
+    pub hysteresis: f32,
+}
+
+impl ThermalLimit {
+    /// Thread cap to apply given the current cap and package temperature.
+    fn next_cap(&self, cap: Option<u64>, temperature: f32, max_threads: u64) -> Option<u64> {
+        let step = (max_threads / 4).max(1);
+        let current = cap.unwrap_or(max_threads);
+        if temperature >= self.limit {
+            Some(current.saturating_sub(step))
+        } else if temperature <= self.limit - self.hysteresis && cap.is_some() {
+            let raised = current + step;
+            (raised < max_threads).then_some(raised)
+        } else {
+            cap
+        }
+    }
+
+    pub fn spawn(self, governor: Arc<ThreadGovernor>) {
+        tokio::spawn(async move {
+            let mut components = Components::new_with_refreshed_list();
+            let mut interval = tokio::time::interval(CHECK_INTERVAL);
+            let mut cap = None;
+            loop {
+                interval.tick().await;
+                components.refresh();
+                let Some(temperature) = cpu_temperature(&components) else {
+                    warn!("no CPU temperature sensor found, thermal throttling disabled");
+                    governor.set_cap("thermal", None);
+                    return;
+                };
+
+                let next = self.next_cap(cap, temperature, governor.max_threads());
+                if next != cap {
+                    match next {
+                        Some(threads) if threads < cap.unwrap_or(u64::MAX) => warn!(
+                            "CPU at {:.1}°C (limit {:.1}°C), throttling to {} mining threads",
+                            temperature, self.limit, threads
+                        ),
+                        Some(threads) => info!("CPU cooled to {:.1}°C, raising to {} mining threads", temperature, threads),
+                        None => info!("CPU cooled to {:.1}°C, thermal throttling lifted", temperature),
+                    }
+                    cap = next;
+                    governor.set_cap("thermal", cap);
+                }
+            }
+        });
+    }
+}
+
+/// Hottest CPU reading, from sysinfo first and `/sys/class/thermal` otherwise.
+pub fn cpu_temperature(components: &Components) -> Option<f32> {
+    components
+        .list()
+        .iter()
+        .filter(|component| {
+            let label = component.label().to_ascii_lowercase();
+            CPU_LABELS.iter().any(|cpu| label.contains(cpu))
+        })
+        .map(|component| component.temperature())
+        .filter(|temperature| temperature.is_finite() && *temperature > 0.0)
+        .reduce(f32::max)
+        .or_else(|| sysfs_temperature(Path::new(SYSFS_THERMAL)))
+}
+
+fn sysfs_temperature(root: &Path) -> Option<f32> {
+    std::fs::read_dir(root)
+        .ok()?
+        .flatten()
+        .filter(|zone| zone.file_name().to_string_lossy().starts_with("thermal_zone"))
+        .filter_map(|zone| {
+            let kind = std::fs::read_to_string(zone.path().join("type")).ok()?.trim().to_ascii_lowercase();
+            if !CPU_ZONES.iter().any(|cpu| kind.contains(cpu)) {
+                return None;
+            }
+            let millidegrees: f32 = std::fs::read_to_string(zone.path().join("temp")).ok()?.trim().parse().ok()?;
+            Some(millidegrees / 1000.0)
+        })
+        .reduce(f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thermal_hysteresis() {
+        let limit = ThermalLimit { limit: 85.0, hysteresis: 5.0 };
+
+        assert_eq!(limit.next_cap(None, 70.0, 16), None);
+        assert_eq!(limit.next_cap(None, 85.0, 16), Some(12));
+        assert_eq!(limit.next_cap(Some(12), 90.0, 16), Some(8));
+        // Between the limit and the hysteresis band the cap holds.
+        assert_eq!(limit.next_cap(Some(8), 82.0, 16), Some(8));
+        assert_eq!(limit.next_cap(Some(8), 80.0, 16), Some(12));
+        assert_eq!(limit.next_cap(Some(12), 75.0, 16), None);
+        assert_eq!(limit.next_cap(Some(0), 99.0, 16), Some(0));
+        assert_eq!(limit.next_cap(None, 90.0, 2), Some(1));
+    }
+
+    #[test]
+    fn test_sysfs_temperature() {
+        let root = tempfile::tempdir().unwrap();
+        for (zone, kind, temp) in [("thermal_zone0", "acpitz", "95000"), ("thermal_zone1", "x86_pkg_temp", "71500")] {
+            let dir = root.path().join(zone);
+            std::fs::create_dir(&dir).unwrap();
+            std::fs::write(dir.join("type"), format!("{kind}\n")).unwrap();
+            std::fs::write(dir.join("temp"), format!("{temp}\n")).unwrap();
+        }
+        assert_eq!(sysfs_temperature(root.path()), Some(71.5));
+    }
+}
@@ -0,0 +1,154 @@
+use anyhow::{anyhow, Context, Result};
+use rustls_021::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls_021::{Certificate, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+// TLS trust configuration for the NockPool API client: extra CA bundles for
+// private relays and SHA-256 pins on either the whole certificate or its
+// SubjectPublicKeyInfo. reqwest 0.11 is built on rustls 0.21, which is why
+// that version is pulled in here under its own name.
+
+/// A SHA-256 pin, written as `spki:<hex>` or `cert:<hex>`. Colons in the hex are
+/// ignored so `openssl x509 -fingerprint -sha256` output can be pasted as-is.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CertPin {
+    Spki([u8; 32]),
+    Cert([u8; 32]),
+}
+
+impl CertPin {
+    pub fn parse(pin: &str) -> Result<Self> {
+        let (kind, digest) = pin
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Invalid pin '{}': expected spki:<sha256-hex> or cert:<sha256-hex>", pin))?;
+        let digest: String = digest.chars().filter(|c| *c != ':').collect();
+        let digest: [u8; 32] = hex::decode(&digest)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| anyhow!("Invalid pin '{}': digest must be 32 bytes of hex", pin))?;
+
+        match kind.to_ascii_lowercase().as_str() {
+            "spki" => Ok(CertPin::Spki(digest)),
+            "cert" => Ok(CertPin::Cert(digest)),
+            other => Err(anyhow!("Invalid pin '{}': unknown pin type '{}'", pin, other)),
+        }
+    }
+
+    fn matches(&self, cert_der: &[u8]) -> bool {
+        match self {
+            CertPin::Cert(digest) => Sha256::digest(cert_der).as_slice() == digest,
+            CertPin::Spki(digest) => X509Certificate::from_der(cert_der)
+                .map(|(_, cert)| Sha256::digest(cert.public_key().raw).as_slice() == digest)
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// Chain validation against the trust store, followed by a pin check on
+/// any certificate in the presented chain.
+struct PinningVerifier {
+    inner: WebPkiVerifier,
+    pins: Vec<CertPin>,
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls_021::Error> {
+        let verified = self
+            .inner
+            .verify_server_cert(end_entity, intermediates, server_name, scts, ocsp_response, now)?;
+
+        if self.pins.is_empty() {
+            return Ok(verified);
+        }
+
+        let pinned = std::iter::once(end_entity)
+            .chain(intermediates)
+            .any(|cert| self.pins.iter().any(|pin| pin.matches(&cert.0)));
+        if pinned {
+            Ok(verified)
+        } else {
+            Err(rustls_021::Error::General(
+                "server certificate does not match any configured pin".to_string(),
+            ))
+        }
+    }
+}
+
+fn load_ca_file(roots: &mut RootCertStore, path: &Path) -> Result<()> {
+    let pem = std::fs::read(path).with_context(|| format!("Failed to read CA file {}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut pem.as_slice())
+        .with_context(|| format!("Failed to parse CA file {}", path.display()))?;
+    if certs.is_empty() {
+        return Err(anyhow!("CA file {} contains no certificates", path.display()));
+    }
+    for cert in certs {
+        roots
+            .add(&Certificate(cert))
+            .with_context(|| format!("Invalid certificate in CA file {}", path.display()))?;
+    }
+    Ok(())
+}
+
+pub fn client_config(ca_file: Option<&Path>, pins: &[CertPin]) -> Result<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(ta.subject, ta.spki, ta.name_constraints)
+    }));
+    if let Some(path) = ca_file {
+        load_ca_file(&mut roots, path)?;
+    }
+
+    let verifier = PinningVerifier {
+        inner: WebPkiVerifier::new(roots, None),
+        pins: pins.to_vec(),
+    };
+
+    Ok(ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pins_match_generated_certificate() {
+        let certified = rcgen::generate_simple_self_signed(vec!["relay.local".to_string()]).unwrap();
+        let cert_der = certified.cert.der().to_vec();
+
+        let cert_pin = CertPin::parse(&format!("cert:{}", hex::encode(Sha256::digest(&cert_der)))).unwrap();
+        let spki_pin = CertPin::parse(&format!(
+            "SPKI:{}",
+            hex::encode(Sha256::digest(certified.key_pair.public_key_der()))
+        ))
+        .unwrap();
+        assert!(cert_pin.matches(&cert_der));
+        assert!(spki_pin.matches(&cert_der));
+
+        // openssl-style colon separated fingerprints are accepted too
+        let colon_hex = Sha256::digest(&cert_der)
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(":");
+        assert_eq!(CertPin::parse(&format!("cert:{}", colon_hex)).unwrap(), cert_pin);
+
+        assert!(!CertPin::Cert([0; 32]).matches(&cert_der));
+        assert!(CertPin::parse("sha1:abcd").is_err());
+        assert!(CertPin::parse("cert:abcd").is_err());
+    }
+}
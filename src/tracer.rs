@@ -3,26 +3,373 @@
 // performance. It uses the `tracing` ecosystem, which provides structured,
 // level-based logging.
 
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::IsTerminal;
+use std::sync::{Mutex, OnceLock};
+
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::span;
 use tracing_subscriber::fmt::format::Writer;
 use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
-use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
 use tracing_subscriber::registry::LookupSpan;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{fmt, EnvFilter};
 
 use tracing::Level;
 
-pub fn init() {
-    let fmt_layer = fmt::layer().with_ansi(true).event_format(MinimalFormatter);
+use crate::config::{LogArgs, LogFormat};
+use crate::log_file::RotatingFile;
+
+/// Sets up logging to stdout and, with `--log-file`, to a rotating file.
+/// With `--tui` the stdout stream is left to the terminal UI, which shows
+/// the recent lines in its own pane. With `--otlp-endpoint` spans are also
+/// exported to an OpenTelemetry collector.
+pub fn init(config: &LogArgs, tui: bool) -> anyhow::Result<()> {
+    let format = config.log_format;
+    // Colours only make sense for people reading a terminal.
+    let ansi = format == LogFormat::Text && std::io::stdout().is_terminal();
+    let fmt_layer = (config.log_stdout && !tui)
+        .then(|| fmt::layer().with_ansi(ansi).event_format(EventFormatter(format)));
+
+    let file_layer = match &config.log_file {
+        Some(path) => {
+            let file = RotatingFile::open(path.clone(), config.log_rotate, config.log_keep, config.log_compress)?;
+            Some(fmt::layer().with_ansi(false).with_writer(Mutex::new(file)).event_format(EventFormatter(format)))
+        }
+        None => None,
+    };
+
+    let otlp_layer = match &config.otlp_endpoint {
+        Some(endpoint) => Some(otlp_layer(endpoint)?),
+        None => None,
+    };
 
     let filter = EnvFilter::builder()
         .with_default_directive("info".parse().expect("default log directive is invalid"))
         .from_env_lossy();
 
     tracing_subscriber::registry()
+        .with((format != LogFormat::Text).then_some(SpanFields))
         .with(fmt_layer)
+        .with(file_layer)
+        .with(otlp_layer)
+        .with(RecentLogs)
         .with(filter)
         .init();
+    Ok(())
+}
+
+/// The OTLP span pipeline, kept so its queue can be flushed on the way out.
+static OTLP_PROVIDER: OnceLock<TracerProvider> = OnceLock::new();
+
+/// Sends the spans still queued for the OTLP collector; call before exiting.
+pub async fn shutdown() {
+    let Some(provider) = OTLP_PROVIDER.get().cloned() else {
+        return;
+    };
+    // Shutting down blocks until the exporter, which runs on this runtime, is done.
+    if let Ok(Err(e)) = tokio::task::spawn_blocking(move || provider.shutdown()).await {
+        eprintln!("Failed to flush spans to the OTLP collector: {}", e);
+    }
+}
+
+/// Span layer exporting over OTLP/gRPC. Spans are batched and sent in the
+/// background, so a slow or missing collector never holds up mining.
+fn otlp_layer<S>(endpoint: &str) -> anyhow::Result<impl Layer<S>>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    let exporter = SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|e| anyhow::anyhow!("Failed to set up OTLP exporter for {}: {}", endpoint, e))?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new(vec![
+            KeyValue::new("service.name", "nockpool-miner"),
+            KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+        ]))
+        .build();
+    let tracer = provider.tracer("nockpool-miner");
+    let _ = OTLP_PROVIDER.set(provider.clone());
+    opentelemetry::global::set_tracer_provider(provider);
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// How many log lines are kept for the dashboard.
+const RECENT_LOG_LINES: usize = 200;
+
+static RECENT_LOGS: Mutex<VecDeque<LogLine>> = Mutex::new(VecDeque::new());
+
+/// A log event as shown on the dashboard.
+#[derive(Clone, Debug, Serialize)]
+pub struct LogLine {
+    pub time: chrono::DateTime<chrono::Utc>,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+/// The most recent log lines, oldest first.
+pub fn recent_lines() -> Vec<LogLine> {
+    RECENT_LOGS.lock().expect("log buffer poisoned").iter().cloned().collect()
+}
+
+/// Keeps the last `RECENT_LOG_LINES` events in memory.
+struct RecentLogs;
+
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            let _ = write!(self.fields, " {}={}", field.name(), value);
+        }
+    }
+}
+
+impl<S: tracing::Subscriber> Layer<S> for RecentLogs {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let line = LogLine {
+            time: chrono::Utc::now(),
+            level: event.metadata().level().to_string(),
+            target: event.metadata().target().to_string(),
+            message: visitor.message + &visitor.fields,
+        };
+
+        let mut lines = RECENT_LOGS.lock().expect("log buffer poisoned");
+        if lines.len() == RECENT_LOG_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+}
+
+/// Dispatches to the formatter for the chosen `--log-format`.
+struct EventFormatter(LogFormat);
+
+impl<S, N> FormatEvent<S, N> for EventFormatter
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        writer: Writer<'_>,
+        event: &tracing::Event<'_>,
+    ) -> std::fmt::Result {
+        match self.0 {
+            LogFormat::Text => MinimalFormatter.format_event(ctx, writer, event),
+            LogFormat::Json => format_json(ctx, writer, event),
+            LogFormat::Logfmt => format_logfmt(ctx, writer, event),
+        }
+    }
+}
+
+/// Collects an event's fields as JSON values, keeping their types.
+#[derive(Default)]
+struct JsonVisitor(Map<String, Value>);
+
+impl Visit for JsonVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().to_string(), Value::String(format!("{:?}", value)));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::String(value.to_string()));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+}
+
+/// Keeps each span's fields, with their types, for the structured formats.
+struct SpanFields;
+
+/// The fields recorded on a span so far.
+struct RecordedFields(Map<String, Value>);
+
+impl<S> Layer<S> for SpanFields
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = JsonVisitor::default();
+        attrs.record(&mut fields);
+        span.extensions_mut().insert(RecordedFields(fields.0));
+    }
+
+    // Fields such as `outcome` are only filled in once the work is done.
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(RecordedFields(fields)) = extensions.get_mut::<RecordedFields>() {
+            let mut visitor = JsonVisitor(std::mem::take(fields));
+            values.record(&mut visitor);
+            *fields = visitor.0;
+        }
+    }
+}
+
+/// Keys every structured record starts with. Span fields of the same name
+/// are prefixed with their span's name instead of replacing these.
+const RECORD_KEYS: [&str; 4] = ["timestamp", "level", "target", "spans"];
+
+/// Timestamp, level, target, enclosing spans with their fields, and the
+/// event's own fields; inner spans win over outer ones and the event over
+/// both. Kernel `slogger` output goes in its own `slogger` field instead of
+/// `message` so it can be filtered apart from the miner's logs.
+fn structured_record<S, N>(ctx: &FmtContext<'_, S, N>, event: &tracing::Event<'_>) -> Map<String, Value>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    let metadata = event.metadata();
+    let mut record = Map::new();
+    record.insert(
+        "timestamp".to_string(),
+        chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true).into(),
+    );
+    record.insert("level".to_string(), metadata.level().as_str().to_lowercase().into());
+    record.insert("target".to_string(), metadata.target().into());
+
+    if let Some(scope) = ctx.event_scope() {
+        let mut spans = Vec::new();
+        for span in scope.from_root() {
+            spans.push(Value::from(span.name()));
+            if let Some(RecordedFields(fields)) = span.extensions().get::<RecordedFields>() {
+                for (key, value) in fields {
+                    let key = match RECORD_KEYS.contains(&key.as_str()) {
+                        true => format!("{}.{}", span.name(), key),
+                        false => key.clone(),
+                    };
+                    record.insert(key, value.clone());
+                }
+            }
+        }
+        record.insert("spans".to_string(), spans.into());
+    }
+
+    let mut fields = JsonVisitor::default();
+    event.record(&mut fields);
+    let mut fields = fields.0;
+    if metadata.target() == "slogger" {
+        if let Some(message) = fields.remove("message") {
+            record.insert("slogger".to_string(), message);
+        }
+    }
+    record.extend(fields);
+    record
+}
+
+fn format_json<S, N>(ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &tracing::Event<'_>) -> std::fmt::Result
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    let record = structured_record(ctx, event);
+    let line = serde_json::to_string(&record).map_err(|_| std::fmt::Error)?;
+    writeln!(writer, "{}", line)
+}
+
+fn format_logfmt<S, N>(ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &tracing::Event<'_>) -> std::fmt::Result
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    let mut record = structured_record(ctx, event);
+    // The well-known keys lead, in a fixed order, so lines are easy to scan.
+    let mut ordered: Vec<(String, Value)> = ["timestamp", "level", "target", "spans", "message", "slogger"]
+        .iter()
+        .filter_map(|key| record.remove(*key).map(|value| (key.to_string(), value)))
+        .collect();
+    ordered.extend(record);
+
+    let mut line = String::new();
+    for (key, value) in &ordered {
+        let value = match value {
+            Value::String(value) => value.clone(),
+            Value::Array(items) => items.iter().filter_map(Value::as_str).collect::<Vec<_>>().join(">"),
+            other => other.to_string(),
+        };
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        let key = if key == "message" { "msg" } else { key.as_str() };
+        line.push_str(key);
+        line.push('=');
+        line.push_str(&logfmt_value(&value));
+    }
+    writeln!(writer, "{}", line)
+}
+
+/// Quotes a logfmt value when it contains spaces, quotes, `=` or control characters.
+fn logfmt_value(value: &str) -> String {
+    let needs_quotes = value.is_empty() || value.chars().any(|c| c == ' ' || c == '"' || c == '=' || c == '\\' || c.is_control());
+    if !needs_quotes {
+        return value.to_string();
+    }
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{{{:04x}}}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
 }
 
 struct MinimalFormatter;
@@ -40,28 +387,33 @@ where
     ) -> std::fmt::Result {
         let level = *event.metadata().level();
         let level_str = match level {
-            Level::TRACE => "\x1B[36mT\x1B[0m",
-            Level::DEBUG => "\x1B[34mD\x1B[0m",
-            Level::INFO => "\x1B[32mI\x1B[0m",
-            Level::WARN => "\x1B[33mW\x1B[0m",
-            Level::ERROR => "\x1B[31mE\x1B[0m",
+            Level::TRACE => "T",
+            Level::DEBUG => "D",
+            Level::INFO => "I",
+            Level::WARN => "W",
+            Level::ERROR => "E",
         };
 
-        // Get level color code for potential use with slogger
-        let level_color = match level {
+        // Colour codes are left out entirely when the output isn't a terminal.
+        let ansi = writer.has_ansi_escapes();
+        let paint = |code: &'static str| if ansi { code } else { "" };
+        let reset = paint("\x1B[0m");
+
+        // Get level color code for the level letter and for slogger
+        let level_color = paint(match level {
             Level::TRACE => "\x1B[36m", // Cyan
             Level::DEBUG => "\x1B[34m", // Blue
             Level::INFO => "\x1B[32m",  // Green
             Level::WARN => "\x1B[33m",  // Yellow
             Level::ERROR => "\x1B[31m", // Red
-        };
+        });
 
-        write!(writer, "{} ", level_str)?;
+        write!(writer, "{level_color}{level_str}{reset} ")?;
 
         // simple, shorter timestamp (HH:mm:ss)
         let now = chrono::Local::now();
         let time_str = now.format("%H:%M:%S").to_string();
-        write!(writer, "\x1B[38;5;246m({time_str})\x1B[0m ")?;
+        write!(writer, "{}({time_str}){reset} ", paint("\x1B[38;5;246m"))?;
 
         let target = event.metadata().target();
 
@@ -71,7 +423,7 @@ where
             // this mimics the behavior of slogging in urbit
             write!(writer, "{}", level_color)?;
             ctx.field_format().format_fields(writer.by_ref(), event)?;
-            write!(writer, "\x1B[0m")?;
+            write!(writer, "{reset}")?;
 
             return writeln!(writer);
         }
@@ -104,11 +456,72 @@ where
         };
 
         // Write the simplified target in grey and italics
-        write!(writer, "\x1B[3;90m{}\x1B[0m: ", simplified_target)?;
+        write!(writer, "{}{}{reset}: ", paint("\x1B[3;90m"), simplified_target)?;
 
         // Write the fields (the actual log message)
         ctx.field_format().format_fields(writer.by_ref(), event)?;
 
         writeln!(writer)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_structured_records_carry_span_fields() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::registry()
+            .with(SpanFields)
+            .with(fmt::layer().with_writer(move || writer.clone()).event_format(EventFormatter(LogFormat::Json)));
+        tracing::subscriber::with_default(subscriber, || {
+            let template = tracing::info_span!("template_received", commit = "c0ffee");
+            let _template = template.enter();
+            let attempt = tracing::info_span!(
+                "mining_attempt",
+                thread = 3u64,
+                target = "00ff",
+                outcome = tracing::field::Empty,
+            );
+            let _attempt = attempt.enter();
+            attempt.record("outcome", "solved");
+            tracing::info!(nonce = 7u64, "solution found");
+        });
+
+        let line = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let record: Value = serde_json::from_str(line.trim()).unwrap();
+        assert_eq!(record["spans"], serde_json::json!(["template_received", "mining_attempt"]));
+        assert_eq!(record["commit"], "c0ffee");
+        assert_eq!(record["thread"], 3);
+        assert_eq!(record["outcome"], "solved");
+        assert_eq!(record["nonce"], 7);
+        assert_eq!(record["message"], "solution found");
+        assert_eq!(record["mining_attempt.target"], "00ff");
+        assert_eq!(record["target"], module_path!());
+    }
+
+    #[test]
+    fn test_logfmt_value_quoting() {
+        assert_eq!(logfmt_value("miner"), "miner");
+        assert_eq!(logfmt_value(""), "\"\"");
+        assert_eq!(logfmt_value("solution found on thread=3"), "\"solution found on thread=3\"");
+        assert_eq!(logfmt_value("say \"hi\"\n"), "\"say \\\"hi\\\"\\n\"");
+    }
+}
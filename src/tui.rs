@@ -0,0 +1,252 @@
+use anyhow::{anyhow, Result};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use std::io::{stdout, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+use crate::governor::ThreadGovernor;
+use crate::status::{ConnectionState, MinerStatus, StatusSnapshot};
+use crate::tracer;
+
+// Full-screen terminal dashboard for operators at the console. It replaces
+// the normal log stream (the log pane shows the same lines) and runs on its
+// own OS thread so a slow terminal never stalls the mining loop.
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+const KEY_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+pub struct Tui {
+    pub governor: Arc<ThreadGovernor>,
+    pub status: Arc<MinerStatus>,
+    pub worker: String,
+}
+
+/// The running terminal UI. Dropping it closes the UI and restores the
+/// terminal, for when the miner stops on its own.
+pub struct TuiHandle {
+    closing: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    quit_rx: oneshot::Receiver<Result<()>>,
+}
+
+impl TuiHandle {
+    /// Resolves once the operator quits, or with the error that ended the UI.
+    pub async fn quit(&mut self) -> Result<()> {
+        (&mut self.quit_rx).await.unwrap_or_else(|_| Err(anyhow!("terminal UI stopped unexpectedly")))
+    }
+}
+
+impl Drop for TuiHandle {
+    fn drop(&mut self) {
+        self.closing.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Puts the terminal back the way we found it, even if drawing panics.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(stdout(), EnterAlternateScreen, Hide)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn duration(secs: f64) -> String {
+    let secs = secs as u64;
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m {s}s"),
+        (h, m, _) => format!("{h}h {m}m"),
+    }
+}
+
+fn fit(line: &str, width: usize) -> String {
+    line.chars().take(width).collect()
+}
+
+impl Tui {
+    /// Runs the dashboard until the operator quits, which the returned
+    /// handle reports so the miner can shut down the normal way.
+    pub fn spawn(self) -> TuiHandle {
+        let closing = Arc::new(AtomicBool::new(false));
+        let (quit_tx, quit_rx) = oneshot::channel();
+        let thread = std::thread::Builder::new()
+            .name("tui".to_string())
+            .spawn({
+                let closing = closing.clone();
+                move || {
+                    let result = self.run(&closing).map_err(|e| e.context("terminal UI failed"));
+                    // Nobody waiting means the miner is already stopping.
+                    let _ = quit_tx.send(result);
+                }
+            })
+            .expect("failed to start terminal UI thread");
+        TuiHandle { closing, thread: Some(thread), quit_rx }
+    }
+
+    fn run(&self, closing: &AtomicBool) -> Result<()> {
+        let _guard = TerminalGuard::enter()?;
+        let mut redraw = true;
+        let mut last_draw = Instant::now();
+        loop {
+            if closing.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            if redraw || last_draw.elapsed() >= REFRESH_INTERVAL {
+                self.draw()?;
+                redraw = false;
+                last_draw = Instant::now();
+            }
+            if !event::poll(KEY_POLL_INTERVAL)? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                KeyCode::Char('p') => self.governor.set_paused(!self.governor.paused()),
+                KeyCode::Char('+') | KeyCode::Char('=') | KeyCode::Up => self.governor.adjust_threads(1),
+                KeyCode::Char('-') | KeyCode::Down => self.governor.adjust_threads(-1),
+                KeyCode::Char('n') => self.status.set_network_only(!self.status.network_only()),
+                _ => continue,
+            }
+            redraw = true;
+        }
+    }
+
+    fn lines(&self, snapshot: &StatusSnapshot, height: usize) -> Vec<String> {
+        let state = if self.governor.paused() {
+            "PAUSED"
+        } else if self.governor.target() == 0 {
+            "IDLE"
+        } else {
+            "MINING"
+        };
+        let mut lines = vec![
+            format!(
+                " nockpool-miner v{} | {} | {} {}/{} threads | up {}",
+                env!("CARGO_PKG_VERSION"),
+                self.worker,
+                state,
+                snapshot.threads.len(),
+                self.governor.max_threads(),
+                duration(snapshot.uptime_secs as f64),
+            ),
+            format!(
+                " proofs/min {:.1} | pool hits {} | network hits {} | template age {} | network-only {}",
+                snapshot.proofs_per_minute,
+                snapshot.pool_hits,
+                snapshot.network_hits,
+                snapshot.template_age_secs.map_or("-".to_string(), |age| duration(age as f64)),
+                if snapshot.network_only { "on" } else { "off" },
+            ),
+        ];
+
+        for connection in &snapshot.connections {
+            let state = match connection.state {
+                ConnectionState::Connecting => "connecting".to_string(),
+                ConnectionState::Connected => "connected".to_string(),
+                ConnectionState::Backoff { retry_in_ms } => format!("backing off, retry in {}", duration(retry_in_ms as f64 / 1000.0)),
+            };
+            lines.push(format!(" {}: {} for {}", connection.name, state, duration(connection.since_secs as f64)));
+        }
+        for session in &snapshot.sessions {
+            lines.push(format!(
+                " shares{}: {} found, {} accepted, {} rejected",
+                session.label.as_ref().map(|label| format!(" key={}", label)).unwrap_or_default(),
+                session.shares.found,
+                session.shares.accepted,
+                session.shares.rejected,
+            ));
+        }
+        if let Some(last) = &snapshot.last_submission {
+            let verdict = match &last.reason {
+                None => "accepted".to_string(),
+                Some(reason) => format!("rejected: {}", reason),
+            };
+            lines.push(format!(" last submission {} at {}", verdict, last.at.with_timezone(&chrono::Local).format("%H:%M:%S")));
+        }
+
+        lines.push(String::new());
+        lines.push(format!(" {:<8}{:<14}{:>10}{:>12}{:>12}  {}", "THREAD", "KEY", "ATTEMPTS", "CURRENT", "LAST", "RESULT"));
+        let thread_rows = height.saturating_sub(lines.len() + 4) / 2;
+        for thread in snapshot.threads.iter().take(thread_rows) {
+            lines.push(format!(
+                " {:<8}{:<14}{:>10}{:>12}{:>12}  {}",
+                thread.id,
+                thread.session.as_deref().unwrap_or("-"),
+                thread.attempts,
+                duration(thread.attempt_secs),
+                thread.last_attempt_secs.map_or("-".to_string(), duration),
+                thread.last_result.map_or("-", |result| result.as_str()),
+            ));
+        }
+        if snapshot.threads.len() > thread_rows {
+            lines.push(format!(" ... {} more", snapshot.threads.len() - thread_rows));
+        }
+
+        lines.push(String::new());
+        let log_rows = height.saturating_sub(lines.len() + 1);
+        let logs = tracer::recent_lines();
+        for line in logs.iter().skip(logs.len().saturating_sub(log_rows)) {
+            lines.push(format!(
+                " {} {:<5} {}",
+                line.time.with_timezone(&chrono::Local).format("%H:%M:%S"),
+                line.level,
+                line.message
+            ));
+        }
+        lines
+    }
+
+    fn draw(&self) -> Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+        let snapshot = self.status.snapshot();
+        let footer = " [p] pause/resume  [+/-] threads  [n] network-only  [q] quit";
+
+        let mut out = stdout();
+        for (row, line) in self.lines(&snapshot, height).iter().take(height.saturating_sub(1)).enumerate() {
+            queue!(out, MoveTo(0, row as u16))?;
+            if row == 0 {
+                queue!(out, SetAttribute(Attribute::Reverse), Print(format!("{:<width$}", fit(line, width))), SetAttribute(Attribute::Reset))?;
+            } else {
+                queue!(out, Print(fit(line, width)), Clear(ClearType::UntilNewLine))?;
+            }
+        }
+        queue!(
+            out,
+            Clear(ClearType::FromCursorDown),
+            MoveTo(0, height.saturating_sub(1) as u16),
+            SetAttribute(Attribute::Dim),
+            Print(fit(footer, width)),
+            SetAttribute(Attribute::Reset),
+        )?;
+        out.flush()?;
+        Ok(())
+    }
+}